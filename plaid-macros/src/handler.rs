@@ -45,7 +45,7 @@ impl From<syn::ItemFn> for Signature {
         // Make sure signature matches:
         //  #[attrs]
         //  async fn $ident($arg1: &mut RequestContext<$global_ctx_type, $local_ctx_type>, $arg2: RouteParameters)
-        //      -> Result<Response, $err_type>
+        //      -> Result<impl IntoResponse, $err_type>
         if func.sig.constness.is_some() {
             panic!("Shouldn't be a const function");
         }
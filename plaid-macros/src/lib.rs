@@ -80,8 +80,15 @@ pub fn handler(
                 &self,
                 #ctx_arg_name: #ctx_arg_type,
                 #param_arg_name: #param_arg_type,
-            ) -> #return_type {
-                #body
+            ) -> Result<Response, #err_type> {
+                // The function can return any `Result<impl IntoResponse, _>`,
+                // so give the body its return type and convert the response
+                fn returns<F: std::future::Future<Output = #return_type>>(body: F) -> F {
+                    body
+                }
+                returns(async move #body)
+                    .await
+                    .map(IntoResponse::into_response)
             }
         }
     };
@@ -97,7 +104,7 @@ mod route_definition;
 /// that connect to each endpoint.
 ///
 /// Format:
-/// ```rust
+/// ```ignore
 ///
 /// plaid::route_definition! {
 ///     {
@@ -146,9 +153,10 @@ mod route_definition;
 ///     - `METHOD` is one of `GET`, `PUT`, `PATCH`, `POST`, or `DELETE`
 ///     - path parts are either a string literal (for a constant route
 /// component) or a parameter and type, like `param_name{type}` (much like the
-/// router path input definition), where the type is a supported router
-/// `plaid::routes::tree::Parameter` (`i32`, `u32`, or `String`, which will be
-/// used as `&str` as an input on the client method). For extensibility, you may
+/// router path input definition), where the type is one of the router's
+/// built-in parameter types (`String`, which will be used as `&str` as an
+/// input on the client method, the integer and float primitives, `bool` or
+/// `Uuid`). For extensibility, you may
 /// specify two types as `param_name{client_type|router_type}` to use another
 /// type of compatible serialization with a router type, which must be one of
 /// the previously defined types.
@@ -338,7 +338,7 @@ impl Route {
                 PathPart::Param {
                     ident, router_ty, ..
                 } => {
-                    format!(":{}{{{}}}", ident.to_string(), router_ty)
+                    format!(":{}{{{}}}", ident, router_ty)
                 }
             })
             .collect::<Vec<_>>();
@@ -351,23 +351,13 @@ impl Route {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct FieldSet {
     auth: Option<(syn::Ident, AuthType)>, // token also points to auth
     body: Option<(syn::Ident, Mime)>,
     query: Option<(syn::Ident, syn::Type)>,
 }
 
-impl Default for FieldSet {
-    fn default() -> Self {
-        Self {
-            auth: None,
-            body: None,
-            query: None,
-        }
-    }
-}
-
 impl FieldSet {
     fn from_fields<I: Iterator<Item = Field>>(fields: I) -> Self {
         let mut set = FieldSet::default();
@@ -445,7 +435,7 @@ enum FieldType {
 
 #[derive(Debug)]
 enum Mime {
-    Json(syn::Type),
+    Json(Box<syn::Type>),
     Bytes,
     Empty,
 }
@@ -453,7 +443,7 @@ enum Mime {
 impl Mime {
     fn new(outer: syn::Ident, inner: Option<syn::Type>) -> Self {
         match outer.to_string().as_str() {
-            "Json" => Mime::Json(inner.expect("MIME Json requires an innner type")),
+            "Json" => Mime::Json(Box::new(inner.expect("MIME Json requires an innner type"))),
             "Bytes" => {
                 if inner.is_some() {
                     panic!("MIME Bytes does not take an inner type");
@@ -545,7 +535,9 @@ impl PathPart {
     }
 }
 
-const ROUTER_TYPES: &[&str] = &["u32", "i32", "String"];
+const ROUTER_TYPES: &[&str] = &[
+    "String", "u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "f32", "f64", "bool", "Uuid",
+];
 
 impl syn::parse::Parse for PathPart {
     fn parse(input: syn::parse::ParseStream) -> syn::parse::Result<Self> {
@@ -579,6 +571,9 @@ impl syn::parse::Parse for PathPart {
                         None
                     }
                 })
+                // Custom hints aren't registered on the generated router, so
+                // custom types must be given with a built-in router type,
+                // like `{Color|String}`
                 .expect("unsupported router type");
 
             Ok(PathPart::Param {
@@ -589,3 +584,28 @@ impl syn::parse::Parse for PathPart {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router_ty(part: &str) -> String {
+        match syn::parse_str::<PathPart>(part).unwrap() {
+            PathPart::Param { router_ty, .. } => router_ty,
+            PathPart::Literal(_) => panic!("expected a parameter"),
+        }
+    }
+
+    #[test]
+    fn path_parts_expand_router_types() {
+        assert_eq!("Uuid", router_ty("id{Uuid}"));
+        assert_eq!("String", router_ty("color{Color|String}"));
+        assert_eq!("u64", router_ty("id{UserId|u64}"));
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported router type")]
+    fn path_parts_reject_custom_router_types() {
+        router_ty("color{Color}");
+    }
+}
@@ -31,7 +31,7 @@ impl Default for RequestIdConfiguration {
     fn default() -> Self {
         Self {
             // tracing: true,
-            mode: RequestIdMode::UUID,
+            mode: RequestIdMode::Uuid,
         }
     }
 }
@@ -39,7 +39,7 @@ impl Default for RequestIdConfiguration {
 impl RequestIdConfiguration {
     /// Set id generation mode to UUID
     pub fn uuid_v4(mut self) -> Self {
-        self.mode = RequestIdMode::UUID;
+        self.mode = RequestIdMode::Uuid;
         self
     }
 
@@ -66,7 +66,7 @@ impl<G, L> RequestId<G, L> {
 
     fn generate(&self) -> String {
         match self.config.mode {
-            RequestIdMode::UUID => uuid::Uuid::new_v4().to_string(),
+            RequestIdMode::Uuid => uuid::Uuid::new_v4().to_string(),
         }
     }
 }
@@ -114,7 +114,7 @@ pub trait RequestIdContext {
 }
 
 enum RequestIdMode {
-    UUID,
+    Uuid,
     // Base64(usize),
     // Hex(usize),
 }
@@ -0,0 +1,392 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{self, HeaderValue};
+
+use crate::prelude::*;
+
+/// An entity tag identifying a version of a resource, as sent in an `ETag`
+/// header. Weak tags (`W/"..."`) only claim the versions are equivalent, not
+/// byte-for-byte identical, so they can't be used for ranges or `If-Match`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// A strong tag. Quotes in `tag` are removed.
+    pub fn strong(tag: &str) -> Self {
+        Self {
+            tag: tag.replace('"', ""),
+            weak: false,
+        }
+    }
+
+    /// A weak tag. Quotes in `tag` are removed.
+    pub fn weak(tag: &str) -> Self {
+        Self {
+            tag: tag.replace('"', ""),
+            weak: true,
+        }
+    }
+
+    /// A strong tag from a hash of some bytes (e.g. a buffered body)
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::strong(&format!("{:x}", md5::compute(bytes)))
+    }
+
+    /// Parse a single tag, like `"abc"` or `W/"abc"`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, value),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(Self {
+            tag: String::from(tag),
+            weak,
+        })
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Whether two tags match with the strong comparison (both strong and
+    /// equal)
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Whether two tags match with the weak comparison (equal, ignoring
+    /// whether they're weak)
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    pub(crate) fn to_header(&self) -> Option<HeaderValue> {
+        HeaderValue::from_str(&self.to_string()).ok()
+    }
+}
+
+impl std::fmt::Display for ETag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// The outcome of evaluating a request's conditional headers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// Handle the request normally
+    Proceed,
+    /// The client's copy is current (304 Not Modified)
+    NotModified,
+    /// A precondition failed (412 Precondition Failed)
+    Failed,
+}
+
+/// Evaluate a request's `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` headers against the current version of a resource, in
+/// the order given by RFC 9110. `exists` is whether the resource has a
+/// current representation, which is what `*` matches (even without a tag).
+pub fn evaluate(
+    request: &HttpRequest,
+    exists: bool,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> Precondition {
+    let headers = request.headers();
+    let is_read = request.method() == Method::GET || request.method() == Method::HEAD;
+
+    if let Some(if_match) = header_str(headers, header::IF_MATCH) {
+        if !matches_any(if_match, exists, etag, ETag::strong_eq) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = header_date(headers, header::IF_UNMODIFIED_SINCE) {
+        if last_modified.is_some_and(|modified| seconds(modified) > seconds(since)) {
+            return Precondition::Failed;
+        }
+    }
+
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        if matches_any(if_none_match, exists, etag, ETag::weak_eq) {
+            return if is_read {
+                Precondition::NotModified
+            } else {
+                Precondition::Failed
+            };
+        }
+    } else if let Some(since) = header_date(headers, header::IF_MODIFIED_SINCE) {
+        if is_read && last_modified.is_some_and(|modified| seconds(modified) <= seconds(since)) {
+            return Precondition::NotModified;
+        }
+    }
+
+    Precondition::Proceed
+}
+
+/// Build the response for a failed precondition, or `None` to proceed. A 304
+/// repeats the validators (`ETag` and `Last-Modified`).
+pub fn respond(
+    precondition: Precondition,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> Option<Response> {
+    let status = match precondition {
+        Precondition::Proceed => return None,
+        Precondition::NotModified => Status::NOT_MODIFIED,
+        Precondition::Failed => Status::PRECONDITION_FAILED,
+    };
+    let mut resp = HttpResponse::new(HttpBody::empty());
+    *resp.status_mut() = status;
+    if status == Status::NOT_MODIFIED {
+        set_validators(resp.headers_mut(), etag, last_modified);
+    }
+    Some(Response::Custom(resp))
+}
+
+/// Set the `ETag` and `Last-Modified` headers
+pub(crate) fn set_validators(
+    headers: &mut hyper::HeaderMap,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) {
+    if let Some(etag) = etag.and_then(ETag::to_header) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+}
+
+/// Answer a `GET` or `HEAD` request's conditional headers from the
+/// validators on a handler's (successful) response, optionally adding a
+/// strong `ETag` to buffered responses that don't have one. Other methods
+/// have already made their changes by now, so they're left to
+/// `RequestContext::preconditions`.
+pub(crate) async fn apply(
+    request: &HttpRequest,
+    response: HttpResponse,
+    auto_etag: bool,
+) -> HttpResponse {
+    let is_read = request.method() == Method::GET || request.method() == Method::HEAD;
+    if response.status() != Status::OK || !is_read {
+        return response;
+    }
+
+    let response = if auto_etag && !response.headers().contains_key(header::ETAG) {
+        with_etag(response).await
+    } else {
+        response
+    };
+
+    let headers = response.headers();
+    let etag = header_str(headers, header::ETAG).and_then(ETag::parse);
+    let last_modified = header_date(headers, header::LAST_MODIFIED);
+    let precondition = evaluate(request, true, etag.as_ref(), last_modified);
+    if precondition == Precondition::Proceed {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    parts.status = match precondition {
+        Precondition::NotModified => Status::NOT_MODIFIED,
+        _ => Status::PRECONDITION_FAILED,
+    };
+    // Nothing about the (dropped) body applies anymore
+    for name in [
+        header::CONTENT_LENGTH,
+        header::CONTENT_TYPE,
+        header::CONTENT_RANGE,
+        header::CONTENT_ENCODING,
+    ] {
+        parts.headers.remove(name);
+    }
+    if parts.status == Status::PRECONDITION_FAILED {
+        parts.headers.remove(header::ETAG);
+        parts.headers.remove(header::LAST_MODIFIED);
+    }
+    HttpResponse::from_parts(parts, HttpBody::empty())
+}
+
+/// Add an `ETag` hashed from a buffered body. Streamed bodies are left as
+/// they are. If the body can't be read, the response becomes a 500.
+async fn with_etag(response: HttpResponse) -> HttpResponse {
+    use hyper::body::HttpBody as _;
+
+    if response.body().size_hint().exact().is_none() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    match hyper::body::to_bytes(body).await {
+        Ok(bytes) => {
+            if let Some(etag) = ETag::from_bytes(&bytes).to_header() {
+                parts.headers.insert(header::ETAG, etag);
+            }
+            HttpResponse::from_parts(parts, HttpBody::from(bytes))
+        }
+        Err(e) => {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to buffer response body for etag: {}", e);
+            // The body is gone, so don't send its headers with an empty one
+            HttpResponse::from(respond::error())
+        }
+    }
+}
+
+/// Whether a list of tags matches the current tag, or is `*` and the
+/// resource exists
+fn matches_any(
+    list: &str,
+    exists: bool,
+    etag: Option<&ETag>,
+    eq: fn(&ETag, &ETag) -> bool,
+) -> bool {
+    if list.trim() == "*" {
+        return exists;
+    }
+    etag.is_some_and(|etag| {
+        list.split(',')
+            .filter_map(ETag::parse)
+            .any(|tag| eq(&tag, etag))
+    })
+}
+
+fn header_str(headers: &hyper::HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn header_date(headers: &hyper::HeaderMap, name: header::HeaderName) -> Option<SystemTime> {
+    header_str(headers, name).and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+/// A time in whole seconds, the precision of HTTP dates
+pub(crate) fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut request = hyper::Request::builder().method(method).uri("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(HttpBody::empty()).unwrap()
+    }
+
+    #[test]
+    fn etags_parse() {
+        assert_eq!(Some(ETag::strong("abc")), ETag::parse("\"abc\""));
+        assert_eq!(Some(ETag::weak("abc")), ETag::parse(" W/\"abc\""));
+        assert_eq!(None, ETag::parse("abc"));
+        assert_eq!("W/\"abc\"", ETag::weak("abc").to_string());
+        assert!(ETag::weak("a").weak_eq(&ETag::strong("a")));
+        assert!(!ETag::weak("a").strong_eq(&ETag::strong("a")));
+    }
+
+    #[test]
+    fn preconditions_evaluate() {
+        let etag = ETag::strong("v2");
+        let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let before = httpdate::fmt_http_date(modified - Duration::from_secs(60));
+        let at = httpdate::fmt_http_date(modified);
+
+        for (method, headers, expected) in [
+            (Method::GET, vec![], Precondition::Proceed),
+            (
+                Method::GET,
+                vec![("if-none-match", "\"v1\", W/\"v2\"")],
+                Precondition::NotModified,
+            ),
+            (
+                Method::GET,
+                vec![("if-none-match", "\"v1\"")],
+                Precondition::Proceed,
+            ),
+            (
+                Method::PUT,
+                vec![("if-none-match", "*")],
+                Precondition::Failed,
+            ),
+            (
+                Method::GET,
+                vec![("if-modified-since", at.as_str())],
+                Precondition::NotModified,
+            ),
+            (
+                Method::GET,
+                vec![("if-modified-since", before.as_str())],
+                Precondition::Proceed,
+            ),
+            // If-None-Match takes precedence over If-Modified-Since
+            (
+                Method::GET,
+                vec![
+                    ("if-none-match", "\"v1\""),
+                    ("if-modified-since", at.as_str()),
+                ],
+                Precondition::Proceed,
+            ),
+            (
+                Method::PUT,
+                vec![("if-match", "\"v2\"")],
+                Precondition::Proceed,
+            ),
+            (
+                Method::PUT,
+                vec![("if-match", "W/\"v2\"")],
+                Precondition::Failed,
+            ),
+            (
+                Method::PUT,
+                vec![("if-unmodified-since", before.as_str())],
+                Precondition::Failed,
+            ),
+            (
+                Method::PUT,
+                vec![("if-unmodified-since", at.as_str())],
+                Precondition::Proceed,
+            ),
+        ] {
+            let request = request(method, &headers);
+            assert_eq!(
+                expected,
+                evaluate(&request, true, Some(&etag), Some(modified)),
+                "{:?}",
+                headers
+            );
+        }
+
+        // `*` matches any current representation, tagged or not
+        for (headers, exists, expected) in [
+            (("if-match", "*"), true, Precondition::Proceed),
+            (("if-match", "*"), false, Precondition::Failed),
+            (("if-none-match", "*"), true, Precondition::Failed),
+            (("if-none-match", "*"), false, Precondition::Proceed),
+        ] {
+            let request = request(Method::PUT, &[headers]);
+            assert_eq!(
+                expected,
+                evaluate(&request, exists, None, None),
+                "{:?}",
+                headers
+            );
+        }
+    }
+}
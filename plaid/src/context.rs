@@ -1,7 +1,12 @@
-use hyper::body::{Buf, Bytes};
+use hyper::body::Bytes;
 use std::sync::Arc;
 
-use super::HttpRequest;
+use crate::conditional::{self, ETag};
+use crate::multipart::{Multipart, MultipartError, MultipartLimits};
+use crate::negotiation::Accept;
+use crate::prelude::*;
+use crate::responses::{IntoProblem, Problem};
+use crate::routes::RouteMatch;
 
 /// # Request Context
 ///
@@ -39,27 +44,40 @@ use super::HttpRequest;
 /// (and to cut down on handler arguments)
 ///
 ///
-/// ## Not Included
+/// ## Route
 ///
-/// It should be noted that some information associated with the request is not
-/// included in this context, like the `RequestParameters`. These are generated
-/// by the router when parsing the url path, which happens after middleware
-/// processing and couldn't be included here. If you need access to route
-/// parameters in your middlware, please raise an issue.
+/// The `route` field holds the route the request matched (see `RouteMatch`):
+/// the template it was added to the router with (e.g. `/users/:id{i32}`) and
+/// the `RouteParameters` parsed from the path. This is useful for things like
+/// metrics labels, tracing span names or authorization by resource id.
 ///
-/// TODO: Future improvement: SubRouters as well as Handlers as routes? Then
-/// middleware could be stored on a particular route only
+/// The server resolves the route before the request is passed to any
+/// middleware, so it is available throughout the stack. It is `None` when no
+/// handler matches the request's path and method. Since middleware may rewrite
+/// the request, the router matches it again before calling the handler and
+/// replaces the field with the final match.
+///
+/// Middleware that only applies to some routes can be added as a layer on a
+/// nested `Router` (see `Router::layer` and `Router::nest`).
 ///
 pub struct RequestContext<GlobalCtx, LocalCtx> {
     pub global: Arc<GlobalCtx>,
     pub local: LocalCtx,
     pub request: HttpRequest,
+    pub route: Option<RouteMatch>,
 }
 
 impl<G, L> RequestContext<G, L> {
     /// Consume the body of a request and return it's bytes
-    pub async fn body(&mut self) -> Result<Bytes, hyper::Error> {
-        hyper::body::to_bytes(self.request.body_mut()).await
+    ///
+    /// If the request has a body limit (see `Server::body_limit`,
+    /// `Router::body_limit` and `RouteOptions::body_limit`), reading stops as
+    /// soon as the body is over it, and the router answers the request with
+    /// `respond::payload_too_large` unless the handler (or error handler)
+    /// already returned a 413, e.g. a problem body from
+    /// `Router::problem_errors`.
+    pub async fn body(&mut self) -> Result<Bytes, BodyError> {
+        crate::limits::read(&mut self.request).await
     }
 
     /// Consume the body of a request and deserialize it to some type using
@@ -68,16 +86,101 @@ impl<G, L> RequestContext<G, L> {
     where
         T: serde::de::DeserializeOwned + Send,
     {
-        // let body = self.body().await?;
-        let reader = hyper::body::aggregate(self.request.body_mut())
-            .await
-            .map_err(JsonError::ReadBody)?
-            .reader();
-        let deserializer = &mut serde_json::Deserializer::from_reader(reader);
+        let body = self.body().await?;
+        let deserializer = &mut serde_json::Deserializer::from_slice(&body);
         let body: T =
             serde_path_to_error::deserialize(deserializer).map_err(JsonError::DeserializeBody)?;
 
-        return Ok(body);
+        Ok(body)
+    }
+
+    /// Like [`RequestContext::body_json`], but fails with
+    /// `JsonError::UnsupportedMediaType` (without reading the body) unless the
+    /// request's `Content-Type` is `application/json` or another JSON type
+    /// (like `application/merge-patch+json`)
+    pub async fn body_json_strict<T>(&mut self) -> Result<T, JsonError>
+    where
+        T: serde::de::DeserializeOwned + Send,
+    {
+        let content_type = self
+            .request
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok());
+        if !content_type.is_some_and(is_json) {
+            return Err(JsonError::UnsupportedMediaType(
+                content_type.map(String::from),
+            ));
+        }
+        self.body_json().await
+    }
+
+    /// Consume the body of a request and deserialize it to some type from an
+    /// `application/x-www-form-urlencoded` form using `serde_urlencoded`
+    pub async fn body_form<T>(&mut self) -> Result<T, FormError>
+    where
+        T: serde::de::DeserializeOwned + Send,
+    {
+        let body = self.body().await?;
+        serde_urlencoded::from_bytes(&body).map_err(FormError::DeserializeBody)
+    }
+
+    /// Consume the body of a request as text, decoded with the charset in its
+    /// `Content-Type` (UTF-8 if there isn't one)
+    pub async fn body_text(&mut self) -> Result<String, TextError> {
+        let encoding = match content_type_param(&self.request, "charset") {
+            Some(charset) => encoding_rs::Encoding::for_label(charset.as_bytes())
+                .ok_or_else(|| TextError::UnknownCharset(String::from(charset)))?,
+            None => encoding_rs::UTF_8,
+        };
+        let body = self.body().await?;
+        encoding
+            .decode_without_bom_handling_and_without_replacement(&body)
+            .map(String::from)
+            .ok_or_else(|| TextError::DecodeBody(encoding.name()))
+    }
+
+    /// Read a `multipart/form-data` body (e.g. a form with file uploads) one
+    /// part at a time, with limits on the size of each part. See
+    /// [`Multipart`].
+    pub fn multipart(&mut self, limits: MultipartLimits) -> Result<Multipart, MultipartError> {
+        Multipart::new(&mut self.request, limits)
+    }
+
+    /// Evaluate the request's conditional headers against the current version
+    /// of the resource (see [`conditional::evaluate`]). Returns the 304 or 412
+    /// response to send instead of handling the request, if any. Use this
+    /// before making changes for unsafe methods like `PUT`, which the router
+    /// doesn't check.
+    pub fn preconditions(
+        &self,
+        exists: bool,
+        etag: Option<&ETag>,
+        last_modified: Option<std::time::SystemTime>,
+    ) -> Option<Response> {
+        let precondition = conditional::evaluate(&self.request, exists, etag, last_modified);
+        conditional::respond(precondition, etag, last_modified)
+    }
+
+    /// Parse the request's `Accept` header
+    pub fn accept(&self) -> Accept {
+        Accept::from_request(&self.request)
+    }
+
+    /// Pick the offered media type (e.g. `application/json`) the request
+    /// prefers, using the `Accept` header's quality values. Earlier offers win
+    /// ties. Returns `None` if none of them are acceptable.
+    pub fn negotiate<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        self.accept().negotiate(offered)
+    }
+
+    /// The id of the last server-sent event the client received, sent when it
+    /// reconnects to an event stream
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.request
+            .headers()
+            .get("last-event-id")
+            .and_then(|id| id.to_str().ok())
     }
 
     pub fn query<T>(&self) -> Result<T, serde_urlencoded::de::Error>
@@ -89,8 +192,33 @@ impl<G, L> RequestContext<G, L> {
     }
 }
 
+#[derive(Debug)]
+pub enum BodyError {
+    ReadBody(hyper::Error),
+    /// The body is over the request's limit (in bytes)
+    PayloadTooLarge(usize),
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::ReadBody(ref e) => write!(f, "Error reading body: {}", e),
+            BodyError::PayloadTooLarge(max_bytes) => {
+                write!(f, "Body is larger than {} bytes", max_bytes)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+#[derive(Debug)]
 pub enum JsonError {
     ReadBody(hyper::Error),
+    /// The body is over the request's limit (in bytes)
+    PayloadTooLarge(usize),
+    /// The request's `Content-Type` (if it has one) isn't JSON
+    UnsupportedMediaType(Option<String>),
     DeserializeBody(serde_path_to_error::Error<serde_json::Error>),
 }
 
@@ -98,7 +226,238 @@ impl std::fmt::Display for JsonError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             JsonError::ReadBody(ref e) => write!(f, "Error reading body: {}", e),
+            JsonError::PayloadTooLarge(max_bytes) => {
+                write!(f, "Body is larger than {} bytes", max_bytes)
+            }
+            JsonError::UnsupportedMediaType(Some(ref content_type)) => {
+                write!(f, "Expected a JSON body, got {}", content_type)
+            }
+            JsonError::UnsupportedMediaType(None) => {
+                write!(f, "Expected a JSON body, got no content type")
+            }
             JsonError::DeserializeBody(ref e) => write!(f, "Failed to deserialize body: {}", e),
         }
     }
 }
+
+/// Describe the error as a problem: a 413 if the body was too large, a 415 if
+/// it wasn't JSON, or a 400 otherwise. Bodies that don't deserialize include
+/// the `path` to the offending field (e.g. `items[0].id`).
+impl IntoProblem for JsonError {
+    fn into_problem(self) -> Problem {
+        let status = match self {
+            JsonError::ReadBody(_) | JsonError::DeserializeBody(_) => Status::BAD_REQUEST,
+            JsonError::PayloadTooLarge(_) => Status::PAYLOAD_TOO_LARGE,
+            JsonError::UnsupportedMediaType(_) => Status::UNSUPPORTED_MEDIA_TYPE,
+        };
+        match self {
+            JsonError::DeserializeBody(ref e) => Problem::new(status)
+                .detail(&e.inner().to_string())
+                .extension("path", e.path().to_string()),
+            ref e => Problem::new(status).detail(&e.to_string()),
+        }
+    }
+}
+
+/// Answer the request with a problem details body (`application/problem+json`)
+/// describing the error (see the `IntoProblem` impl)
+impl From<JsonError> for Response {
+    fn from(e: JsonError) -> Self {
+        Response::from(e.into_problem())
+    }
+}
+
+/// Whether a media type is JSON: `application/json`, or one with a `+json`
+/// suffix
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match essence.split_once('/') {
+        Some((type_, subtype)) => {
+            type_.eq_ignore_ascii_case("application")
+                && (subtype.eq_ignore_ascii_case("json")
+                    || subtype.to_ascii_lowercase().ends_with("+json"))
+        }
+        None => false,
+    }
+}
+
+impl From<BodyError> for JsonError {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::ReadBody(e) => JsonError::ReadBody(e),
+            BodyError::PayloadTooLarge(max_bytes) => JsonError::PayloadTooLarge(max_bytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FormError {
+    ReadBody(hyper::Error),
+    /// The body is over the request's limit (in bytes)
+    PayloadTooLarge(usize),
+    DeserializeBody(serde_urlencoded::de::Error),
+}
+
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::ReadBody(ref e) => write!(f, "Error reading body: {}", e),
+            FormError::PayloadTooLarge(max_bytes) => {
+                write!(f, "Body is larger than {} bytes", max_bytes)
+            }
+            FormError::DeserializeBody(ref e) => write!(f, "Failed to deserialize body: {}", e),
+        }
+    }
+}
+
+impl From<BodyError> for FormError {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::ReadBody(e) => FormError::ReadBody(e),
+            BodyError::PayloadTooLarge(max_bytes) => FormError::PayloadTooLarge(max_bytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextError {
+    ReadBody(hyper::Error),
+    /// The body is over the request's limit (in bytes)
+    PayloadTooLarge(usize),
+    /// The `Content-Type`'s charset isn't one we can decode
+    UnknownCharset(String),
+    /// The body isn't valid in its charset (named)
+    DecodeBody(&'static str),
+}
+
+impl std::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextError::ReadBody(ref e) => write!(f, "Error reading body: {}", e),
+            TextError::PayloadTooLarge(max_bytes) => {
+                write!(f, "Body is larger than {} bytes", max_bytes)
+            }
+            TextError::UnknownCharset(ref charset) => write!(f, "Unknown charset: {}", charset),
+            TextError::DecodeBody(charset) => write!(f, "Body is not valid {}", charset),
+        }
+    }
+}
+
+impl From<BodyError> for TextError {
+    fn from(e: BodyError) -> Self {
+        match e {
+            BodyError::ReadBody(e) => TextError::ReadBody(e),
+            BodyError::PayloadTooLarge(max_bytes) => TextError::PayloadTooLarge(max_bytes),
+        }
+    }
+}
+
+/// A parameter of the request's `Content-Type`, like its `charset`
+fn content_type_param<'r>(request: &'r HttpRequest, name: &str) -> Option<&'r str> {
+    let content_type = request.headers().get(hyper::header::CONTENT_TYPE)?;
+    content_type
+        .to_str()
+        .ok()?
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(param, _)| param.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(content_type: &str, body: &'static [u8]) -> RequestContext<(), ()> {
+        let request = hyper::Request::builder()
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(hyper::Body::from(body))
+            .unwrap();
+        crate::testing::context(request)
+    }
+
+    #[tokio::test]
+    async fn forms_work() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Login {
+            user: String,
+            remember: bool,
+        }
+
+        let content_type = "application/x-www-form-urlencoded";
+        let mut ctx = context(content_type, b"user=J%C3%B6rg&remember=true");
+        let login = ctx.body_form::<Login>().await.unwrap();
+        assert_eq!(
+            Login {
+                user: String::from("J\u{f6}rg"),
+                remember: true
+            },
+            login
+        );
+
+        let mut ctx = context(content_type, b"user=J");
+        assert!(matches!(
+            ctx.body_form::<Login>().await,
+            Err(FormError::DeserializeBody(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn strict_json_works() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Order {
+            #[allow(dead_code)]
+            items: Vec<Item>,
+        }
+        #[derive(serde::Deserialize, Debug)]
+        struct Item {
+            #[allow(dead_code)]
+            id: u32,
+        }
+
+        let body = br#"{"items": [{"id": 1}]}"#;
+        let mut ctx = context("application/vnd.api+json", body);
+        assert!(ctx.body_json_strict::<Order>().await.is_ok());
+
+        let mut ctx = context("text/plain", body);
+        let e = ctx.body_json_strict::<Order>().await.unwrap_err();
+        assert!(matches!(e, JsonError::UnsupportedMediaType(Some(ref t)) if t == "text/plain"));
+        let resp = HttpResponse::from(Response::from(e));
+        assert_eq!(Status::UNSUPPORTED_MEDIA_TYPE, resp.status());
+
+        let mut ctx = context("application/json", br#"{"items": [{"id": "a"}]}"#);
+        let e = ctx.body_json_strict::<Order>().await.unwrap_err();
+        let resp = HttpResponse::from(Response::from(e));
+        assert_eq!(Status::BAD_REQUEST, resp.status());
+        assert_eq!(
+            "application/problem+json",
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(400, problem["status"]);
+        assert_eq!("items[0].id", problem["path"]);
+    }
+
+    #[tokio::test]
+    async fn text_works() {
+        let mut ctx = context("text/plain", "J\u{f6}rg".as_bytes());
+        assert_eq!("J\u{f6}rg", ctx.body_text().await.unwrap());
+
+        let mut ctx = context("text/plain; charset=\"ISO-8859-1\"", b"J\xf6rg");
+        assert_eq!("J\u{f6}rg", ctx.body_text().await.unwrap());
+
+        let mut ctx = context("text/plain; charset=utf-8", b"J\xf6rg");
+        assert!(matches!(
+            ctx.body_text().await,
+            Err(TextError::DecodeBody("UTF-8"))
+        ));
+
+        let mut ctx = context("text/plain; charset=klingon", b"");
+        assert!(matches!(
+            ctx.body_text().await,
+            Err(TextError::UnknownCharset(_))
+        ));
+    }
+}
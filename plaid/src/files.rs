@@ -0,0 +1,365 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::conditional::{self, ETag, Precondition};
+use crate::range;
+
+use hyper::header::{self, HeaderValue};
+
+use crate::prelude::*;
+
+/// # Static Files
+///
+/// A [`Handler`] that serves the files in a directory. Add it under a
+/// catch-all route, which is used as the file's path in the directory, and
+/// let the catch-all match a trailing slash too, so subdirectories' index
+/// files can be served. Since catch-alls don't match an empty path, add it
+/// for the directory itself too to serve its index file there:
+///
+/// ```ignore
+/// let files = StaticFiles::new("./public");
+/// router.add(vec![Method::GET], "/assets/", files.clone());
+/// router.add_with_options(
+///     vec![Method::GET],
+///     "/assets/*path",
+///     RouteOptions::new().trailing_slash(TrailingSlash::MatchBoth),
+///     files,
+/// );
+/// ```
+///
+/// Files are streamed with a content type guessed from their extension, and
+/// with `ETag` and `Last-Modified` headers so clients can make conditional
+/// requests (answered with a 304) and request byte ranges (answered with a
+/// 206). Requests for a directory are served its index file (`index.html` by
+/// default), and are redirected to add a trailing slash if they're missing
+/// one.
+///
+/// Paths that could escape the directory (e.g. with `..` segments, or
+/// through a symlink) are never served.
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index: Option<String>,
+    spa_fallback: bool,
+    cache_control: Option<HeaderValue>,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index: Some(String::from("index.html")),
+            spa_fallback: false,
+            cache_control: None,
+        }
+    }
+
+    /// The file served for directories, or `None` to give a 404 for them
+    pub fn index_file(mut self, index: Option<&str>) -> Self {
+        self.index = index.map(String::from);
+        self
+    }
+
+    /// Serve the root's index file for paths that don't exist (and don't have
+    /// a file extension), so client-side routes in a single page app can be
+    /// loaded directly. Missing assets (like `app.js`) still get a 404.
+    pub fn spa_fallback(mut self, enabled: bool) -> Self {
+        self.spa_fallback = enabled;
+        self
+    }
+
+    /// Set a `Cache-Control` header on every file served
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value isn't a valid header value
+    pub fn cache_control(mut self, value: &str) -> Self {
+        let value = HeaderValue::from_str(value)
+            .unwrap_or_else(|e| panic!("Invalid Cache-Control value {:?}: {}", value, e));
+        self.cache_control = Some(value);
+        self
+    }
+
+    /// Find the file to serve for a (decoded) path relative to the root
+    async fn resolve(&self, relative: &str) -> Resolved {
+        let relative = match safe_relative_path(relative) {
+            Some(relative) => relative,
+            None => return Resolved::NotFound,
+        };
+
+        let path = self.root.join(&relative);
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => match &self.index {
+                Some(index) => Resolved::Directory(path.join(index)),
+                None => Resolved::NotFound,
+            },
+            Ok(_) => Resolved::File(path),
+            Err(_) if self.spa_fallback && relative.extension().is_none() => match &self.index {
+                Some(index) => Resolved::File(self.root.join(index)),
+                None => Resolved::NotFound,
+            },
+            Err(_) => Resolved::NotFound,
+        }
+    }
+
+    /// Whether a path is inside the root, once symlinks are resolved
+    async fn contains(&self, path: &Path) -> bool {
+        match (
+            tokio::fs::canonicalize(&self.root).await,
+            tokio::fs::canonicalize(path).await,
+        ) {
+            (Ok(root), Ok(path)) => path.starts_with(root),
+            _ => false,
+        }
+    }
+
+    async fn serve(&self, request: &HttpRequest, path: &Path) -> std::io::Result<Response> {
+        let file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Ok(respond::not_found());
+        }
+        let length = metadata.len();
+        let modified = metadata.modified().ok();
+        let etag = file_etag(length, modified);
+
+        // Answer conditional requests from the client's cache
+        let precondition = conditional::evaluate(request, true, Some(&etag), modified);
+        if let Some(response) = conditional::respond(precondition, Some(&etag), modified) {
+            let mut resp = HttpResponse::from(response);
+            if precondition == Precondition::NotModified {
+                self.set_headers(&mut resp, &etag, modified);
+            }
+            return Ok(Response::Custom(resp));
+        }
+
+        let content_type = mime_guess::from_path(path).first_or_octet_stream();
+        let response = range::serve(
+            request,
+            file,
+            length,
+            content_type.as_ref(),
+            Some(&etag),
+            modified,
+        );
+        let mut resp = HttpResponse::from(response);
+        if resp.status() != Status::RANGE_NOT_SATISFIABLE {
+            self.set_headers(&mut resp, &etag, modified);
+        }
+        Ok(Response::Custom(resp))
+    }
+
+    fn set_headers(&self, resp: &mut HttpResponse, etag: &ETag, modified: Option<SystemTime>) {
+        let headers = resp.headers_mut();
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        conditional::set_validators(headers, Some(etag), modified);
+        if let Some(cache_control) = &self.cache_control {
+            headers.insert(header::CACHE_CONTROL, cache_control.clone());
+        }
+    }
+}
+
+enum Resolved {
+    File(PathBuf),
+    Directory(PathBuf),
+    NotFound,
+}
+
+#[async_trait]
+impl<G, L, E> Handler<G, L, E> for StaticFiles
+where
+    G: Send + Sync + 'static,
+    L: Send + 'static,
+    E: Send + 'static,
+{
+    async fn handle(
+        &self,
+        ctx: &mut RequestContext<G, L>,
+        params: RouteParameters,
+    ) -> Result<Response, E> {
+        // The catch-all is always the last parameter
+        let relative = match params.ordered.last() {
+            Some(Parameter::String(relative)) => relative.as_str(),
+            _ => "",
+        };
+
+        let path = match self.resolve(relative).await {
+            Resolved::File(path) => path,
+            Resolved::Directory(path) => {
+                // Relative links in the index need the trailing slash. The
+                // redirect uses the normalized path, since the raw one could
+                // start with `//` and send the client to another host.
+                let route = ctx.route.as_ref();
+                if let Some(route) = route.filter(|route| !route.path.ends_with('/')) {
+                    let mut location = format!("{}/", route.path);
+                    if let Some(query) = ctx.request.uri().query() {
+                        location.push('?');
+                        location.push_str(query);
+                    }
+                    return Ok(respond::redirect(Status::MOVED_PERMANENTLY, &location));
+                }
+                path
+            }
+            Resolved::NotFound => return Ok(respond::not_found()),
+        };
+        if !self.contains(&path).await {
+            return Ok(respond::not_found());
+        }
+
+        match self.serve(&ctx.request, &path).await {
+            Ok(response) => Ok(response),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(respond::not_found()),
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Failed to serve {}: {}", path.display(), e);
+                Ok(respond::error())
+            }
+        }
+    }
+}
+
+/// Turn a request path into a relative file path, refusing anything that
+/// isn't a plain file name (like `..`, or a drive prefix on Windows)
+fn safe_relative_path(relative: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in relative.split('/').filter(|s| !s.is_empty()) {
+        if segment.contains('\\') || segment.contains('\0') {
+            return None;
+        }
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => path.push(name),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// An `ETag` for a file from its length and modification time
+fn file_etag(length: u64, modified: Option<SystemTime>) -> ETag {
+    let modified = modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos());
+    ETag::strong(&format!("{:x}-{:x}", length, modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum BasicError {}
+
+    async fn call(
+        router: &Router<(), (), BasicError>,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> HttpResponse {
+        let mut request = hyper::Request::builder().uri(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        crate::testing::call(router, request.body(HttpBody::empty()).unwrap()).await
+    }
+
+    async fn body(resp: HttpResponse) -> String {
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn static_files_work() {
+        let root = std::env::temp_dir().join(format!("plaid-static-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("public/css")).unwrap();
+        std::fs::write(root.join("public/index.html"), "<h1>Home</h1>").unwrap();
+        std::fs::write(root.join("public/css/site.css"), "body { color: red; }").unwrap();
+        std::fs::write(root.join("public/css/index.html"), "<h1>Styles</h1>").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+
+        let files = StaticFiles::new(root.join("public")).spa_fallback(true);
+        let routes = |mut router: Router<(), (), BasicError>| {
+            router.add(vec![Method::GET], "/static/", files.clone());
+            router.add_with_options(
+                vec![Method::GET],
+                "/static/*path",
+                RouteOptions::new().trailing_slash(TrailingSlash::MatchBoth),
+                files.clone(),
+            );
+            router
+        };
+        let router = routes(Router::new());
+
+        let resp = call(&router, "/static/css/site.css", &[]).await;
+        assert_eq!(Status::OK, resp.status());
+        assert_eq!("text/css", resp.headers()[header::CONTENT_TYPE]);
+        assert_eq!("20", resp.headers()[header::CONTENT_LENGTH]);
+        assert_eq!("bytes", resp.headers()[header::ACCEPT_RANGES]);
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_owned();
+        let modified = resp.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!("body { color: red; }", body(resp).await);
+
+        // Conditional requests
+        for header in [
+            ("if-none-match", etag.as_str()),
+            ("if-modified-since", &modified),
+        ] {
+            let resp = call(&router, "/static/css/site.css", &[header]).await;
+            assert_eq!(Status::NOT_MODIFIED, resp.status());
+        }
+        let resp = call(
+            &router,
+            "/static/css/site.css",
+            &[("if-none-match", "\"x\"")],
+        )
+        .await;
+        assert_eq!(Status::OK, resp.status());
+
+        // Ranges
+        let resp = call(&router, "/static/css/site.css", &[("range", "bytes=7-11")]).await;
+        assert_eq!(Status::PARTIAL_CONTENT, resp.status());
+        assert_eq!("bytes 7-11/20", resp.headers()[header::CONTENT_RANGE]);
+        assert_eq!("color", body(resp).await);
+        let resp = call(&router, "/static/css/site.css", &[("range", "bytes=-2")]).await;
+        assert_eq!(" }", body(resp).await);
+        let resp = call(&router, "/static/css/site.css", &[("range", "bytes=50-")]).await;
+        assert_eq!(Status::RANGE_NOT_SATISFIABLE, resp.status());
+        assert_eq!("bytes */20", resp.headers()[header::CONTENT_RANGE]);
+        let headers = [("range", "bytes=0-3"), ("if-range", "\"stale\"")];
+        let resp = call(&router, "/static/css/site.css", &headers).await;
+        assert_eq!(Status::OK, resp.status());
+
+        // Directories, fallbacks and traversal
+        let resp = call(&router, "/static/", &[]).await;
+        assert_eq!("<h1>Home</h1>", body(resp).await);
+        for (path, location) in [
+            ("/static/css", "/static/css/"),
+            ("/static/css?v=2", "/static/css/?v=2"),
+            // Redirects never use the raw path, which could lead off-site
+            ("//evil.com/../static/css", "/static/css/"),
+        ] {
+            let resp = call(&router, path, &[]).await;
+            assert_eq!(Status::MOVED_PERMANENTLY, resp.status());
+            assert_eq!(location, resp.headers()[header::LOCATION]);
+        }
+        let resp = call(&router, "/static/css/", &[]).await;
+        assert_eq!(Status::OK, resp.status());
+        assert_eq!("<h1>Styles</h1>", body(resp).await);
+        let resp = call(&router, "/static/app/settings", &[]).await;
+        assert_eq!("<h1>Home</h1>", body(resp).await);
+
+        // Traversal, even when dot segments reach the handler
+        let literal = routes(Router::new().dot_segments(DotSegments::Literal));
+        for path in [
+            "/static/app.js",
+            "/static/../secret.txt",
+            "/static/%2E%2E/secret.txt",
+        ] {
+            assert_eq!(Status::NOT_FOUND, call(&literal, path, &[]).await.status());
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
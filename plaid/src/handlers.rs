@@ -20,21 +20,24 @@ pub trait Handler<GlobalCtx, LocalCtx, Err>: Send + Sync + 'static {
     ) -> Result<Response, Err>;
 }
 
+/// Closures (and functions) can be handlers, returning anything that
+/// implements [`IntoResponse`]
 #[async_trait]
-impl<G, L, E, F, Fut> Handler<G, L, E> for F
+impl<G, L, E, F, Fut, R> Handler<G, L, E> for F
 where
     G: Send + Sync + 'static,
     L: Send + 'static,
     E: Send + 'static,
     F: Fn(&mut RequestContext<G, L>, RouteParameters) -> Fut + Send + Sync + 'static,
-    Fut: Future<Output = Result<Response, E>> + Send + 'static,
+    Fut: Future<Output = Result<R, E>> + Send + 'static,
+    R: IntoResponse,
 {
     async fn handle(
         &self,
         ctx: &mut RequestContext<G, L>,
         params: RouteParameters,
     ) -> Result<Response, E> {
-        (self)(ctx, params).await
+        (self)(ctx, params).await.map(IntoResponse::into_response)
     }
 }
 
@@ -54,7 +57,7 @@ where
 }
 
 // Use same type hints as the impl Handler for F above
-pub fn closure<GlobalCtx, LocalCtx, Err, F, Fut>(
+pub fn closure<GlobalCtx, LocalCtx, Err, F, Fut, R>(
     handler: F,
 ) -> WrappedHandler<GlobalCtx, LocalCtx, Err>
 where
@@ -62,7 +65,8 @@ where
     LocalCtx: Send + 'static,
     Err: Send + 'static,
     F: Fn(&mut RequestContext<GlobalCtx, LocalCtx>, RouteParameters) -> Fut + Send + Sync + 'static,
-    Fut: Future<Output = Result<Response, Err>> + Send + 'static,
+    Fut: Future<Output = Result<R, Err>> + Send + 'static,
+    R: IntoResponse,
 {
     wrapped(handler)
 }
@@ -74,6 +78,28 @@ pub fn default_error_handler<GlobalCtx, LocalCtx, Err>(
     respond::status(Status::INTERNAL_SERVER_ERROR)
 }
 
+/// An error handler answering with the error's problem details (see
+/// `Router::problem_errors`)
+pub fn problem_error_handler<GlobalCtx, LocalCtx, Err: IntoProblem>(
+    _: &mut RequestContext<GlobalCtx, LocalCtx>,
+    e: Err,
+) -> Response {
+    Response::from(e.into_problem())
+}
+
+pub fn default_not_found_handler<GlobalCtx, LocalCtx>(
+    _: &mut RequestContext<GlobalCtx, LocalCtx>,
+) -> Response {
+    respond::not_found()
+}
+
+pub fn default_method_not_allowed_handler<GlobalCtx, LocalCtx>(
+    _: &mut RequestContext<GlobalCtx, LocalCtx>,
+    allowed: &[Method],
+) -> Response {
+    respond::method_not_allowed_with(allowed)
+}
+
 #[cfg(test)]
 mod test {
     use crate::prelude::*;
@@ -123,14 +149,8 @@ mod test {
             super::closure(|_, _| async { Ok(respond::ok()) }), // For closures without hints
             Arc::new(|_: &mut RequestContext<(), ()>, _| Box::pin(async { Ok(respond::ok()) })), // An already pinned closure
         ];
-        let gctx = Arc::new(());
-
         for h in hs {
-            let mut ctx = RequestContext {
-                global: gctx.clone(),
-                local: (),
-                request: HttpRequest::new(hyper::Body::empty()),
-            };
+            let mut ctx = crate::testing::context(HttpRequest::new(hyper::Body::empty()));
             let params = RouteParameters::new();
             let res = h.handle(&mut ctx, params).await;
             let res = hyper::Response::<hyper::Body>::from(res.unwrap());
@@ -10,12 +10,22 @@ pub use async_trait::async_trait;
 pub use plaid_macros::*;
 // pub use tokio::main;
 
+pub mod conditional;
 pub mod context;
+mod files;
 mod handlers;
+mod limits;
 pub mod middleware;
+pub mod multipart;
+pub mod negotiation;
+pub mod range;
 pub mod responses;
 mod routes;
 mod server;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 pub mod prelude {
 
@@ -27,16 +37,28 @@ pub mod prelude {
     pub type Method = hyper::Method;
     pub type Status = hyper::StatusCode;
 
+    pub use super::conditional::ETag;
     pub use super::context::RequestContext;
-    pub use super::responses::{respond, Response};
+    pub use super::files::StaticFiles;
+    pub use super::responses::{
+        respond, Event, IntoProblem, IntoResponse, Json, Problem, Response, Sse, StreamBody,
+    };
     pub use super::server::{Server, ServerError};
 
     pub use super::handlers::*;
     // pub use super::router::handlers::{}
 
-    pub use super::routes::{Parameter, RouteParameters, Router};
+    pub use super::routes::{
+        DotSegments, EncodedSlashes, FromParam, Parameter, ParameterError, ParameterKey,
+        RouteError, RouteMatch, RouteOptions, RouteParameters, Router, TrailingSlash,
+    };
 
     pub use super::middleware::{Middleware, ToMiddleware};
+    pub use super::multipart::{Multipart, MultipartLimits};
+    pub use super::negotiation::Accept;
+
+    #[cfg(feature = "websocket")]
+    pub use super::websocket::{websocket, Message, WebSocket, WebSocketContext};
 
     // pub type Result<Err> = std::result::Result<Response, Err>;
 }
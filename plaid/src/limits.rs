@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures_util::{Stream, TryStreamExt};
+use hyper::body::{Bytes, HttpBody as _};
+
+use crate::context::BodyError;
+use crate::prelude::*;
+
+/// The most bytes a request's body may have, kept in its extensions. Set by
+/// the server, then replaced by the router and route (if they have their own
+/// limits).
+///
+/// The flag is shared with the body's readers (even once the body has been
+/// taken out of the request, like for multipart streams), which set it when
+/// the body goes over the limit so the router can answer with a 413.
+#[derive(Clone, Debug)]
+pub(crate) struct BodyLimit {
+    max_bytes: usize,
+    exceeded: Arc<AtomicBool>,
+}
+
+pub(crate) fn set(request: &mut HttpRequest, max_bytes: usize) {
+    request.extensions_mut().insert(BodyLimit {
+        max_bytes,
+        exceeded: Arc::new(AtomicBool::new(false)),
+    });
+}
+
+pub(crate) fn get(request: &HttpRequest) -> Option<usize> {
+    request
+        .extensions()
+        .get::<BodyLimit>()
+        .map(|limit| limit.max_bytes)
+}
+
+/// The request's limit, if its `Content-Length` says its body is over it
+pub(crate) fn exceeded_by_length(request: &HttpRequest) -> Option<usize> {
+    let max_bytes = get(request)?;
+    let length = request
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+    (length > max_bytes as u64).then_some(max_bytes)
+}
+
+/// The limit that was exceeded while reading the request's body, if any
+pub(crate) fn exceeded(request: &HttpRequest) -> Option<usize> {
+    request
+        .extensions()
+        .get::<BodyLimit>()
+        .filter(|limit| limit.exceeded.load(Ordering::Relaxed))
+        .map(|limit| limit.max_bytes)
+}
+
+/// Take a request's body as a stream of chunks, which fails as soon as it's
+/// over the request's limit (or before reading anything, if its
+/// `Content-Length` is)
+pub(crate) fn stream(
+    request: &mut HttpRequest,
+) -> impl Stream<Item = Result<Bytes, BodyError>> + Send + 'static {
+    let limit = request.extensions().get::<BodyLimit>().cloned();
+    let too_large = exceeded_by_length(request).is_some();
+    let body = std::mem::take(request.body_mut());
+
+    futures_util::stream::try_unfold(
+        (body, limit, 0usize, too_large),
+        |(mut body, limit, read, too_large)| async move {
+            let chunk = match (&limit, too_large) {
+                (Some(limit), true) => return Err(over(limit)),
+                _ => body.data().await.transpose().map_err(BodyError::ReadBody)?,
+            };
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            };
+            let read = read + chunk.len();
+            match &limit {
+                Some(limit) if read > limit.max_bytes => Err(over(limit)),
+                _ => Ok(Some((chunk, (body, limit, read, false)))),
+            }
+        },
+    )
+}
+
+fn over(limit: &BodyLimit) -> BodyError {
+    limit.exceeded.store(true, Ordering::Relaxed);
+    BodyError::PayloadTooLarge(limit.max_bytes)
+}
+
+/// Read a request's whole body, stopping as soon as it's over the request's
+/// limit
+pub(crate) async fn read(request: &mut HttpRequest) -> Result<Bytes, BodyError> {
+    if get(request).is_none() {
+        return hyper::body::to_bytes(request.body_mut())
+            .await
+            .map_err(BodyError::ReadBody);
+    }
+
+    let mut chunks = Box::pin(stream(request));
+    let mut body = Vec::new();
+    while let Some(chunk) = chunks.try_next().await? {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(body))
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use hyper::body::Bytes;
+
+use crate::context::BodyError;
+use crate::prelude::*;
+
+/// # Multipart
+///
+/// A `multipart/form-data` body (e.g. a form with file uploads), read one
+/// part at a time as it's received (see `RequestContext::multipart`):
+///
+/// ```ignore
+/// let mut form = ctx.multipart(MultipartLimits::new().per_part(10 << 20))?;
+/// while let Some(mut part) = form.next_part().await? {
+///     if part.is_file() {
+///         while let Some(chunk) = part.chunk().await? {
+///             file.write_all(&chunk).await?;
+///         }
+///     } else {
+///         let value = part.text().await?;
+///     }
+/// }
+/// ```
+///
+/// The whole body is limited by the request's body limit (see
+/// `Router::body_limit`), and each part can be limited further with
+/// [`MultipartLimits`].
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+}
+
+impl Multipart {
+    pub(crate) fn new(
+        request: &mut HttpRequest,
+        limits: MultipartLimits,
+    ) -> Result<Self, MultipartError> {
+        let boundary = request
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| multer::parse_boundary(content_type).ok())
+            .ok_or(MultipartError::NotMultipart)?;
+
+        let mut size_limit = multer::SizeLimit::new();
+        if let Some(max_bytes) = limits.per_part {
+            size_limit = size_limit.per_field(max_bytes);
+        }
+        for (name, max_bytes) in limits.parts {
+            size_limit = size_limit.for_field(name, max_bytes);
+        }
+        let constraints = multer::Constraints::new().size_limit(size_limit);
+
+        let body = crate::limits::stream(request);
+        Ok(Self {
+            inner: multer::Multipart::with_constraints(body, boundary, constraints),
+        })
+    }
+
+    /// The next part of the body, or `None` once they've all been read. The
+    /// previous part must be dropped first.
+    pub async fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        let part = self
+            .inner
+            .next_field()
+            .await
+            .map_err(MultipartError::from)?;
+        Ok(part.map(|inner| Part { inner }))
+    }
+}
+
+/// A part of a [`Multipart`] body: a form field, or a file if it has a file
+/// name
+pub struct Part {
+    inner: multer::Field<'static>,
+}
+
+impl Part {
+    /// The name of the form field
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// The name of the uploaded file, if the part is a file
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_name().is_some()
+    }
+
+    /// The part's media type (e.g. `image/png`), if it has one
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(|mime| mime.essence_str())
+    }
+
+    pub fn headers(&self) -> &hyper::HeaderMap {
+        self.inner.headers()
+    }
+
+    /// The next chunk of the part's content, or `None` once it's all been read
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        self.inner.chunk().await.map_err(MultipartError::from)
+    }
+
+    /// Read the part's whole content
+    pub async fn bytes(self) -> Result<Bytes, MultipartError> {
+        self.inner.bytes().await.map_err(MultipartError::from)
+    }
+
+    /// Read the part's whole content as text, decoded with the charset in its
+    /// `Content-Type` (UTF-8 by default)
+    pub async fn text(self) -> Result<String, MultipartError> {
+        self.inner.text().await.map_err(MultipartError::from)
+    }
+}
+
+/// Size limits for the parts of a [`Multipart`] body, in bytes
+#[derive(Clone, Debug, Default)]
+pub struct MultipartLimits {
+    per_part: Option<u64>,
+    parts: HashMap<String, u64>,
+}
+
+impl MultipartLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most bytes any part may have
+    pub fn per_part(mut self, max_bytes: u64) -> Self {
+        self.per_part = Some(max_bytes);
+        self
+    }
+
+    /// The most bytes the part for a field may have, replacing `per_part`
+    pub fn part(mut self, name: &str, max_bytes: u64) -> Self {
+        self.parts.insert(String::from(name), max_bytes);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The request's `Content-Type` isn't `multipart/*` with a boundary
+    NotMultipart,
+    ReadBody(hyper::Error),
+    /// The body is over the request's limit (in bytes)
+    PayloadTooLarge(usize),
+    /// A part is over its limit (see [`MultipartLimits`])
+    PartTooLarge {
+        name: Option<String>,
+        max_bytes: u64,
+    },
+    /// The body isn't valid multipart
+    Malformed(multer::Error),
+}
+
+impl From<multer::Error> for MultipartError {
+    fn from(e: multer::Error) -> Self {
+        match e {
+            multer::Error::FieldSizeExceeded { limit, field_name } => {
+                MultipartError::PartTooLarge {
+                    name: field_name,
+                    max_bytes: limit,
+                }
+            }
+            multer::Error::StreamReadFailed(e) => match e.downcast::<BodyError>() {
+                Ok(e) => match *e {
+                    BodyError::ReadBody(e) => MultipartError::ReadBody(e),
+                    BodyError::PayloadTooLarge(max_bytes) => {
+                        MultipartError::PayloadTooLarge(max_bytes)
+                    }
+                },
+                // Errors from the stream can be wrapped more than once
+                Err(e) => match e.downcast::<multer::Error>() {
+                    Ok(e) => MultipartError::from(*e),
+                    Err(e) => MultipartError::Malformed(multer::Error::StreamReadFailed(e)),
+                },
+            },
+            e => MultipartError::Malformed(e),
+        }
+    }
+}
+
+impl std::fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::NotMultipart => write!(f, "Body is not multipart"),
+            MultipartError::ReadBody(ref e) => write!(f, "Error reading body: {}", e),
+            MultipartError::PayloadTooLarge(max_bytes) => {
+                write!(f, "Body is larger than {} bytes", max_bytes)
+            }
+            MultipartError::PartTooLarge { name, max_bytes } => write!(
+                f,
+                "Part {} is larger than {} bytes",
+                name.as_deref().unwrap_or("(unnamed)"),
+                max_bytes
+            ),
+            MultipartError::Malformed(ref e) => write!(f, "Malformed multipart body: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(body: &'static str) -> RequestContext<(), ()> {
+        let request = hyper::Request::builder()
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=X",
+            )
+            .body(HttpBody::from(body.replace('\n', "\r\n")))
+            .unwrap();
+        crate::testing::context(request)
+    }
+
+    const BODY: &str = "--X
+Content-Disposition: form-data; name=\"title\"
+
+Hello
+--X
+Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"
+Content-Type: text/plain
+
+0123456789
+--X--
+";
+
+    #[tokio::test]
+    async fn multipart_works() {
+        let mut ctx = context(BODY);
+        let mut form = ctx.multipart(MultipartLimits::new()).unwrap();
+
+        let part = form.next_part().await.unwrap().unwrap();
+        assert_eq!(Some("title"), part.name());
+        assert!(!part.is_file());
+        assert_eq!("Hello", part.text().await.unwrap());
+
+        let part = form.next_part().await.unwrap().unwrap();
+        assert_eq!(Some("a.txt"), part.file_name());
+        assert_eq!(Some("text/plain"), part.content_type());
+        assert_eq!("0123456789", part.bytes().await.unwrap());
+
+        assert!(form.next_part().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn multipart_limits_work() {
+        let mut ctx = context(BODY);
+        let mut form = ctx
+            .multipart(MultipartLimits::new().part("upload", 4))
+            .unwrap();
+        form.next_part().await.unwrap().unwrap();
+        let part = form.next_part().await.unwrap().unwrap();
+        assert!(matches!(
+            part.bytes().await,
+            Err(MultipartError::PartTooLarge { max_bytes: 4, .. })
+        ));
+
+        let mut ctx = context(BODY);
+        crate::limits::set(&mut ctx.request, 16);
+        let mut form = ctx.multipart(MultipartLimits::new()).unwrap();
+        assert!(matches!(
+            form.next_part().await,
+            Err(MultipartError::PayloadTooLarge(16))
+        ));
+        assert_eq!(Some(16), crate::limits::exceeded(&ctx.request));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::prelude::*;
+
+/// A parsed `Accept` header: a list of media ranges with their quality values
+///
+/// A request without an `Accept` header accepts anything (`*/*`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accept {
+    ranges: Vec<MediaRange>,
+}
+
+/// A single media range from an `Accept` header, like `text/*;q=0.5`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaRange {
+    /// The type, or `*`
+    pub type_: String,
+    /// The subtype, or `*`
+    pub subtype: String,
+    /// Any parameters other than `q`, like `charset`
+    pub params: Vec<(String, String)>,
+    /// The quality value, from 0 (not acceptable) to 1
+    pub q: f32,
+}
+
+impl Accept {
+    /// Parse an `Accept` header's value. Malformed ranges are skipped.
+    pub fn parse(value: &str) -> Self {
+        let ranges = value.split(',').filter_map(MediaRange::parse).collect();
+        Self { ranges }
+    }
+
+    /// Read the `Accept` header(s) of a request
+    pub fn from_request(request: &HttpRequest) -> Self {
+        let values = request
+            .headers()
+            .get_all(hyper::header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<&str>>();
+        if values.is_empty() {
+            Self::any()
+        } else {
+            Self::parse(&values.join(","))
+        }
+    }
+
+    /// Accept anything (`*/*`)
+    pub fn any() -> Self {
+        Self::parse("*/*")
+    }
+
+    /// The media ranges, in the order they were given
+    pub fn ranges(&self) -> &[MediaRange] {
+        &self.ranges
+    }
+
+    /// The quality of a media type (like `application/json`), from the most
+    /// specific range that matches it. Returns 0 if no range matches.
+    pub fn quality(&self, media_type: &str) -> f32 {
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(media_type))
+            .max_by_key(|range| range.specificity())
+            .map_or(0.0, |range| range.q)
+    }
+
+    /// Pick the offered media type with the highest quality, preferring
+    /// earlier offers on ties. Returns `None` if none are acceptable.
+    pub fn negotiate<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&str, f32)> = None;
+        for media_type in offered {
+            let q = self.quality(media_type);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((media_type, q));
+            }
+        }
+        best.map(|(media_type, _)| media_type)
+    }
+}
+
+impl MediaRange {
+    fn parse(range: &str) -> Option<Self> {
+        let mut parts = range.split(';');
+        let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+
+        let mut q = 1.0;
+        let mut params = Vec::new();
+        for param in parts {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim().trim_matches('"')),
+                None => continue,
+            };
+            if name.eq_ignore_ascii_case("q") {
+                q = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|q| (0.0..=1.0).contains(q))?;
+            } else {
+                params.push((name.to_ascii_lowercase(), String::from(value)));
+            }
+        }
+
+        Some(Self {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params,
+            q,
+        })
+    }
+
+    /// Whether the range includes a media type. Parameters on the media type
+    /// (after a `;`) must match the range's parameters, if it has any.
+    pub fn matches(&self, media_type: &str) -> bool {
+        let mut parts = media_type.split(';');
+        let (type_, subtype) = match parts.next().and_then(|essence| essence.split_once('/')) {
+            Some(essence) => essence,
+            None => return false,
+        };
+        let params = parts
+            .filter_map(|param| param.split_once('='))
+            .map(|(name, value)| (name.trim(), value.trim().trim_matches('"')))
+            .collect::<Vec<(&str, &str)>>();
+
+        (self.type_ == "*" || self.type_.eq_ignore_ascii_case(type_.trim()))
+            && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype.trim()))
+            && self.params.iter().all(|(name, value)| {
+                params
+                    .iter()
+                    .any(|(n, v)| n.eq_ignore_ascii_case(name) && v.eq_ignore_ascii_case(value))
+            })
+    }
+
+    /// How specific the range is, so `text/plain;format=flowed` beats
+    /// `text/plain`, which beats `text/*`, which beats `*/*`
+    fn specificity(&self) -> usize {
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ => 2 + self.params.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_works() {
+        let accept = Accept::parse(
+            "text/html, application/json;q=0.8, text/*;q=0.5;charset=utf-8, bad, */*;q=x",
+        );
+        assert_eq!(3, accept.ranges().len());
+        assert_eq!(
+            MediaRange {
+                type_: String::from("text"),
+                subtype: String::from("*"),
+                params: vec![(String::from("charset"), String::from("utf-8"))],
+                q: 0.5,
+            },
+            accept.ranges()[2]
+        );
+        assert!(Accept::parse("").ranges().is_empty());
+    }
+
+    #[test]
+    fn negotiation_works() {
+        let accept = Accept::parse("text/*;q=0.5, application/json, text/csv;q=0");
+        assert_eq!(1.0, accept.quality("application/json"));
+        assert_eq!(0.5, accept.quality("text/plain"));
+        assert_eq!(0.0, accept.quality("text/csv"));
+        assert_eq!(0.0, accept.quality("image/png"));
+
+        assert_eq!(
+            Some("application/json"),
+            accept.negotiate(&["text/plain", "application/json"])
+        );
+        assert_eq!(
+            Some("text/plain"),
+            accept.negotiate(&["text/csv", "text/plain"])
+        );
+        assert_eq!(None, accept.negotiate(&["text/csv", "image/png"]));
+
+        // Ties go to the first offer
+        assert_eq!(
+            Some("text/plain"),
+            Accept::any().negotiate(&["text/plain", "application/json"])
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use std::collections::VecDeque;
+use std::io::SeekFrom;
+use std::time::SystemTime;
+
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderValue};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::conditional::{self, ETag};
+use crate::prelude::*;
+
+/// Requests with more ranges than this are served the whole body, since
+/// that's cheaper than lots of tiny parts
+const MAX_RANGES: usize = 16;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// A range of bytes in a body, with inclusive bounds (like in a `Range`
+/// header)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range
+    pub fn len(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.end - self.start + 1
+        }
+    }
+
+    /// Whether the range has no bytes (its end is before its start)
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+}
+
+/// How to answer a request for a body of some length
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// Send the whole body (there's no `Range` header, or it can't be used)
+    Full,
+    /// Send these ranges (206 Partial Content)
+    Partial(Vec<ByteRange>),
+    /// None of the ranges overlap the body (416 Range Not Satisfiable)
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header, like `bytes=0-99,200-`, for a body of `length`
+/// bytes. Suffix ranges (`bytes=-100`) are the last bytes of the body.
+/// Malformed headers are ignored, as are ranges that don't overlap the body
+/// (unless none of them do). Overlapping or adjacent ranges are merged (as
+/// RFC 7233 allows), so the ranges are returned in order and no byte is sent
+/// twice.
+pub fn parse(value: &str, length: u64) -> RangeRequest {
+    let specs = match value.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return RangeRequest::Full,
+    };
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let (start, end) = match spec.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => return RangeRequest::Full,
+        };
+        let range = match (start, end) {
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(suffix) => Some((length.saturating_sub(suffix), length)),
+                Err(_) => return RangeRequest::Full,
+            },
+            (start, end) => {
+                let start = match start.parse::<u64>() {
+                    Ok(start) => start,
+                    Err(_) => return RangeRequest::Full,
+                };
+                let end = match end {
+                    "" => u64::MAX,
+                    end => match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return RangeRequest::Full,
+                    },
+                };
+                Some((start, end))
+            }
+        };
+        // Clamp to the body, skipping ranges that start past its end
+        if let Some((start, end)) = range {
+            if start < length {
+                ranges.push(ByteRange {
+                    start,
+                    end: end.min(length - 1),
+                });
+            }
+        }
+    }
+
+    let ranges = merge(ranges);
+    if ranges.len() > MAX_RANGES {
+        RangeRequest::Full
+    } else if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+/// Sort ranges by their start, merging any that overlap or are adjacent
+fn merge(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Work out which ranges of a body to send for a request, from its `Range`
+/// and `If-Range` headers. Only `GET` requests get ranges, and with
+/// `If-Range` they're only sent if it matches the body's current `ETag`
+/// (strongly) or `Last-Modified` date.
+pub fn requested(
+    request: &HttpRequest,
+    length: u64,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> RangeRequest {
+    let range = match request.headers().get(header::RANGE) {
+        Some(range) if request.method() == Method::GET => range.to_str().unwrap_or_default(),
+        _ => return RangeRequest::Full,
+    };
+    if let Some(if_range) = request.headers().get(header::IF_RANGE) {
+        let if_range = if_range.to_str().unwrap_or_default();
+        let current = match ETag::parse(if_range) {
+            Some(tag) => etag.is_some_and(|etag| tag.strong_eq(etag)),
+            None => match (httpdate::parse_http_date(if_range), last_modified) {
+                (Ok(date), Some(modified)) => {
+                    conditional::seconds(date) == conditional::seconds(modified)
+                }
+                _ => false,
+            },
+        };
+        if !current {
+            return RangeRequest::Full;
+        }
+    }
+    parse(range, length)
+}
+
+/// Serve a seekable source (like a file) of `length` bytes, with the ranges
+/// the request asks for (see [`requested`]). The source is streamed, and
+/// several ranges are sent as `multipart/byteranges`.
+pub fn serve<R>(
+    request: &HttpRequest,
+    source: R,
+    length: u64,
+    content_type: &str,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> Response
+where
+    R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+{
+    let ranges = match requested(request, length, etag, last_modified) {
+        RangeRequest::Unsatisfiable => return unsatisfiable(length),
+        RangeRequest::Partial(ranges) => ranges,
+        RangeRequest::Full => {
+            let parts = match length {
+                0 => VecDeque::new(),
+                _ => VecDeque::from(vec![Part::Range(ByteRange {
+                    start: 0,
+                    end: length - 1,
+                })]),
+            };
+            let body = StreamBody::new(read_parts(source, parts))
+                .content_type(content_type)
+                .length(length);
+            return with_accept_ranges(Response::Stream(Status::OK, body));
+        }
+    };
+
+    let multipart = Multipart::new(&ranges, length, content_type);
+    let body = StreamBody::new(read_parts(source, multipart.parts))
+        .content_type(&multipart.content_type)
+        .length(multipart.length);
+    let mut resp = HttpResponse::from(Response::Stream(Status::PARTIAL_CONTENT, body));
+    if let Some(content_range) = multipart.content_range {
+        resp.headers_mut()
+            .insert(header::CONTENT_RANGE, content_range);
+    }
+    with_accept_ranges(Response::Custom(resp))
+}
+
+/// Marks a buffered response (like [`Response::Bytes`]) whose ranges the
+/// router can serve. It only advertises `Accept-Ranges: bytes` once it's
+/// known that ranges are served (see [`apply`]).
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AcceptsRanges;
+
+/// Answer a `Range` request from a handler's buffered response, if it allows
+/// ranges (it's marked with [`AcceptsRanges`] or has `Accept-Ranges: bytes`)
+/// and was successful. Such responses advertise `Accept-Ranges: bytes`.
+pub(crate) async fn apply(request: &HttpRequest, mut response: HttpResponse) -> HttpResponse {
+    use hyper::body::HttpBody as _;
+
+    let accepts_ranges = response.extensions().get::<AcceptsRanges>().is_some()
+        || response
+            .headers()
+            .get(header::ACCEPT_RANGES)
+            .is_some_and(|value| value == "bytes");
+    if !accepts_ranges || response.status() != Status::OK {
+        return response;
+    }
+    response
+        .headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let length = match response.body().size_hint().exact() {
+        Some(length) if request.headers().contains_key(header::RANGE) => length,
+        _ => return response,
+    };
+
+    let headers = response.headers();
+    let etag = headers
+        .get(header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .and_then(ETag::parse);
+    let last_modified = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| httpdate::parse_http_date(date).ok());
+    let ranges = match requested(request, length, etag.as_ref(), last_modified) {
+        RangeRequest::Full => return response,
+        RangeRequest::Unsatisfiable => return HttpResponse::from(unsatisfiable(length)),
+        RangeRequest::Partial(ranges) => ranges,
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to buffer response body for ranges: {}", e);
+            return HttpResponse::from(respond::error());
+        }
+    };
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    let multipart = Multipart::new(&ranges, length, content_type);
+
+    let mut partial = Vec::with_capacity(multipart.length as usize);
+    for part in multipart.parts {
+        match part {
+            Part::Header(header) => partial.extend_from_slice(&header),
+            Part::Range(range) => {
+                partial.extend_from_slice(&body[range.start as usize..=range.end as usize])
+            }
+        }
+    }
+
+    parts.status = Status::PARTIAL_CONTENT;
+    // The MD5 was of the whole body
+    parts.headers.remove("content-md5");
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(multipart.length));
+    if let Ok(content_type) = HeaderValue::from_str(&multipart.content_type) {
+        parts.headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    if let Some(content_range) = multipart.content_range {
+        parts.headers.insert(header::CONTENT_RANGE, content_range);
+    }
+    HttpResponse::from_parts(parts, HttpBody::from(partial))
+}
+
+fn unsatisfiable(length: u64) -> Response {
+    let mut resp = HttpResponse::new(HttpBody::empty());
+    *resp.status_mut() = Status::RANGE_NOT_SATISFIABLE;
+    if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", length)) {
+        resp.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    with_accept_ranges(Response::Custom(resp))
+}
+
+fn with_accept_ranges(response: Response) -> Response {
+    let mut resp = HttpResponse::from(response);
+    resp.headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    Response::Custom(resp)
+}
+
+/// A piece of a partial body: either literal bytes (a multipart header) or a
+/// range of the source
+enum Part {
+    Header(Bytes),
+    Range(ByteRange),
+}
+
+/// The layout of a partial response. A single range is sent as it is, with a
+/// `Content-Range` header. Several ranges are sent as `multipart/byteranges`,
+/// each with their own headers.
+struct Multipart {
+    parts: VecDeque<Part>,
+    content_type: String,
+    content_range: Option<HeaderValue>,
+    length: u64,
+}
+
+impl Multipart {
+    fn new(ranges: &[ByteRange], length: u64, content_type: &str) -> Self {
+        let content_range =
+            |range: &ByteRange| format!("bytes {}-{}/{}", range.start, range.end, length);
+
+        if let [range] = ranges {
+            return Self {
+                parts: VecDeque::from(vec![Part::Range(*range)]),
+                content_type: String::from(content_type),
+                content_range: HeaderValue::from_str(&content_range(range)).ok(),
+                length: range.len(),
+            };
+        }
+
+        let boundary = uuid::Uuid::new_v4().simple().to_string();
+        let mut parts = VecDeque::new();
+        let mut total = 0;
+        for (i, range) in ranges.iter().enumerate() {
+            // Each part after the first starts on a new line
+            let header = format!(
+                "{}--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                if i == 0 { "" } else { "\r\n" },
+                boundary,
+                content_type,
+                content_range(range)
+            );
+            total += header.len() as u64 + range.len();
+            parts.push_back(Part::Header(Bytes::from(header)));
+            parts.push_back(Part::Range(*range));
+        }
+        let end = format!("\r\n--{}--\r\n", boundary);
+        total += end.len() as u64;
+        parts.push_back(Part::Header(Bytes::from(end)));
+
+        Self {
+            parts,
+            content_type: format!("multipart/byteranges; boundary={}", boundary),
+            content_range: None,
+            length: total,
+        }
+    }
+}
+
+/// Stream the parts of a body, seeking to and reading each range from the
+/// source in turn
+fn read_parts<R>(
+    source: R,
+    parts: VecDeque<Part>,
+) -> impl futures_util::Stream<Item = std::io::Result<Bytes>> + Send
+where
+    R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+{
+    futures_util::stream::try_unfold(
+        (source, parts, 0u64),
+        |(mut source, mut parts, mut remaining)| async move {
+            loop {
+                if remaining > 0 {
+                    let mut chunk = vec![0; remaining.min(CHUNK_SIZE) as usize];
+                    let read = source.read(&mut chunk).await?;
+                    if read == 0 {
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    }
+                    chunk.truncate(read);
+                    remaining -= read as u64;
+                    return Ok(Some((Bytes::from(chunk), (source, parts, remaining))));
+                }
+                match parts.pop_front() {
+                    Some(Part::Header(header)) => {
+                        return Ok(Some((header, (source, parts, remaining))))
+                    }
+                    Some(Part::Range(range)) => {
+                        source.seek(SeekFrom::Start(range.start)).await?;
+                        remaining = range.len();
+                    }
+                    None => return Ok(None),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parsing_works() {
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 9), range(20, 99)]),
+            parse("bytes=0-9, 20-, -10", 100)
+        );
+        assert_eq!(
+            RangeRequest::Partial(vec![range(0, 19), range(30, 39)]),
+            parse("bytes=30-39, 10-19, 0-9, 35-36", 100)
+        );
+        assert_eq!(
+            RangeRequest::Partial(vec![range(50, 99)]),
+            parse("bytes=50-500, 200-300", 100)
+        );
+        assert_eq!(RangeRequest::Unsatisfiable, parse("bytes=100-", 100));
+        assert_eq!(RangeRequest::Unsatisfiable, parse("bytes=-0", 100));
+        assert_eq!(RangeRequest::Full, parse("bytes=5-1", 100));
+        assert_eq!(RangeRequest::Full, parse("items=0-1", 100));
+        assert_eq!(RangeRequest::Full, parse("bytes=a-b", 100));
+        assert_eq!(RangeRequest::Full, parse("bytes=", 100));
+    }
+
+    #[tokio::test]
+    async fn serving_works() {
+        let request = |range: &str| {
+            hyper::Request::builder()
+                .header(header::RANGE, range)
+                .body(HttpBody::empty())
+                .unwrap()
+        };
+        let source = || std::io::Cursor::new(b"0123456789".to_vec());
+
+        let resp = HttpResponse::from(serve(
+            &request("bytes=2-4"),
+            source(),
+            10,
+            "text/plain",
+            None,
+            None,
+        ));
+        assert_eq!(Status::PARTIAL_CONTENT, resp.status());
+        assert_eq!("bytes 2-4/10", resp.headers()[header::CONTENT_RANGE]);
+        assert_eq!("3", resp.headers()[header::CONTENT_LENGTH]);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("234", body);
+
+        let resp = HttpResponse::from(serve(
+            &request("bytes=0-1,-2"),
+            source(),
+            10,
+            "text/plain",
+            None,
+            None,
+        ));
+        let content_type = resp.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_owned();
+        let length = resp.headers()[header::CONTENT_LENGTH].to_str().unwrap();
+        let length = length.parse::<usize>().unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(length, body.len());
+        assert_eq!(
+            format!(
+                "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+                 --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+                 --{b}--\r\n",
+                b = boundary
+            ),
+            body
+        );
+    }
+}
@@ -1,6 +1,18 @@
+mod into_response;
+mod problem;
+mod sse;
+
+use futures_util::{Stream, TryStreamExt};
+use hyper::body::Bytes;
 use hyper::header::HeaderValue;
+use tokio::io::AsyncRead;
 
+use crate::conditional::ETag;
+use crate::negotiation::Accept;
 use crate::prelude::*;
+pub use into_response::{IntoResponse, Json};
+pub use problem::{IntoProblem, Problem};
+pub use sse::{Event, Sse};
 
 /// Shortcuts for generating [`Response`]s
 pub mod respond {
@@ -20,6 +32,16 @@ pub mod respond {
         Response::Empty(Status::METHOD_NOT_ALLOWED)
     }
 
+    /// A 405 response with an `Allow` header listing the methods the target
+    /// resource supports
+    pub fn method_not_allowed_with(allowed: &[Method]) -> Response {
+        let mut resp = HttpResponse::new(HttpBody::empty());
+        *resp.status_mut() = Status::METHOD_NOT_ALLOWED;
+        resp.headers_mut()
+            .insert(hyper::header::ALLOW, allow_header(allowed));
+        Response::Custom(resp)
+    }
+
     pub fn not_found() -> Response {
         Response::Empty(Status::NOT_FOUND)
     }
@@ -66,6 +88,29 @@ pub mod respond {
         }
     }
 
+    /// Serialize a body as JSON, form-urlencoded (if it's a flat struct or
+    /// map) and plain text (scalars as their value, anything else as pretty
+    /// JSON). The router sends whichever the request's `Accept` header
+    /// prefers (JSON on ties), or a 406 if it accepts none of them.
+    pub fn negotiated<T: serde::Serialize>(
+        status: Status,
+        body: &T,
+    ) -> Result<Response, ResponseError> {
+        let value = serde_json::to_value(body).map_err(ResponseError::SerializeJson)?;
+        let mut representations = vec![(CONTENT_TYPE_JSON, value.to_string())];
+        if let Ok(form) = serde_urlencoded::to_string(body) {
+            representations.push((CONTENT_TYPE_FORM, form));
+        }
+        let text = match value {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(text) => text,
+            serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+            _ => serde_json::to_string_pretty(&value).map_err(ResponseError::SerializeJson)?,
+        };
+        representations.push((CONTENT_TYPE_TEXT, text));
+        Ok(Response::Negotiated(status, representations))
+    }
+
     // &[u8] instead? But how to stop from copying?
     pub fn bytes(
         status: Status,
@@ -85,6 +130,53 @@ pub mod respond {
         }
     }
 
+    /// Stream a body from chunks of bytes, as `application/octet-stream` (see
+    /// [`StreamBody`] to set the content type or length)
+    pub fn stream<S, E>(status: Status, stream: S) -> Response
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Response::Stream(status, StreamBody::new(stream))
+    }
+
+    /// Stream a body from a reader (e.g. a file), as
+    /// `application/octet-stream` (see [`StreamBody`] to set the content type
+    /// or length)
+    pub fn reader<R>(status: Status, reader: R) -> Response
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        Response::Stream(status, StreamBody::from_reader(reader))
+    }
+
+    /// Send server-sent events from a stream, with keep-alive comments every
+    /// 15 seconds (see [`Sse`] to change the interval)
+    pub fn sse<S, E>(events: S) -> Response
+    where
+        S: Stream<Item = Result<Event, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Response::from(Sse::new(events))
+    }
+
+    /// Redirect to `location` with some 3XX status
+    pub fn redirect(status: Status, location: &str) -> Response {
+        let mut resp = HttpResponse::new(HttpBody::empty());
+        *resp.status_mut() = status;
+        match HeaderValue::from_str(location) {
+            Ok(location) => {
+                resp.headers_mut().insert(hyper::header::LOCATION, location);
+            }
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Failed to convert redirect location to header: {}", e);
+                return error();
+            }
+        }
+        Response::Custom(resp)
+    }
+
     pub fn unauthorized() -> Response {
         let mut resp = HttpResponse::new(HttpBody::empty());
         *resp.status_mut() = Status::UNAUTHORIZED;
@@ -101,26 +193,29 @@ pub mod respond {
     /// router section of our service will be simple if this handles errors
     /// internally.
     pub(crate) fn options(options: &[Method]) -> Response {
-        let allow_methods = options
+        let mut resp = HttpResponse::new(HttpBody::empty());
+        *resp.status_mut() = Status::NO_CONTENT;
+        resp.headers_mut()
+            .insert(hyper::header::ALLOW, allow_header(options));
+
+        Response::Custom(resp)
+    }
+
+    /// The value of an `Allow` header for a list of methods
+    pub(crate) fn allow_header(methods: &[Method]) -> HeaderValue {
+        let allow_methods = methods
             .iter()
             .map(|m| m.as_str())
             .collect::<Vec<&str>>()
             .join(", ");
 
-        let allow_header = HeaderValue::from_str(&allow_methods)
+        HeaderValue::from_str(&allow_methods)
             .map_err(ResponseError::ToHeaderValue)
             .unwrap_or_else(|e| {
                 #[cfg(feature = "tracing")]
                 tracing::warn!("Failed to convert response to http: {}", e);
                 HeaderValue::from_static("")
-            });
-
-        let mut resp = HttpResponse::new(HttpBody::empty());
-        *resp.status_mut() = Status::NO_CONTENT;
-        resp.headers_mut()
-            .insert(hyper::header::ALLOW, allow_header);
-
-        Response::Custom(resp)
+            })
     }
 }
 
@@ -134,6 +229,11 @@ pub enum Response {
         md5: Option<String>,
     },
     Json(Status, String),
+    /// A body in several media types, in order of preference. The one sent is
+    /// picked with the request's `Accept` header (see `respond::negotiated`).
+    Negotiated(Status, Vec<(&'static str, String)>),
+    /// A body sent as it is produced, rather than buffered
+    Stream(Status, StreamBody),
 
     Custom(HttpResponse),
 }
@@ -149,9 +249,128 @@ impl Response {
                 md5: _,
             } => *status,
             Response::Json(ref s, _) => *s,
+            Response::Negotiated(ref s, _) => *s,
+            Response::Stream(ref s, _) => *s,
             Response::Custom(http) => http.status(),
         }
     }
+
+    /// Replace the response's status
+    pub fn with_status(self, status: Status) -> Response {
+        match self {
+            Response::Empty(_) => Response::Empty(status),
+            Response::Text(_, text) => Response::Text(status, text),
+            Response::Bytes { body, md5, .. } => Response::Bytes { status, body, md5 },
+            Response::Json(_, json) => Response::Json(status, json),
+            Response::Negotiated(_, representations) => {
+                Response::Negotiated(status, representations)
+            }
+            Response::Stream(_, body) => Response::Stream(status, body),
+            Response::Custom(mut resp) => {
+                *resp.status_mut() = status;
+                Response::Custom(resp)
+            }
+        }
+    }
+
+    /// Set the response's `ETag`. The response is converted to a `Custom`
+    /// one (`Negotiated` responses are converted with their preferred
+    /// representation, so use `Router::auto_etag` for those instead).
+    pub fn with_etag(self, etag: &ETag) -> Response {
+        let mut resp = HttpResponse::from(self);
+        crate::conditional::set_validators(resp.headers_mut(), Some(etag), None);
+        Response::Custom(resp)
+    }
+
+    /// Set the response's `Last-Modified` date (see [`Response::with_etag`])
+    pub fn with_last_modified(self, last_modified: std::time::SystemTime) -> Response {
+        let mut resp = HttpResponse::from(self);
+        crate::conditional::set_validators(resp.headers_mut(), None, Some(last_modified));
+        Response::Custom(resp)
+    }
+
+    /// Pick the representation of a `Negotiated` response that the request
+    /// accepts, or a 406 if it accepts none of them. Other responses are
+    /// returned as they are.
+    pub fn negotiate(self, request: &HttpRequest) -> Response {
+        let (status, representations) = match self {
+            Response::Negotiated(status, representations) => (status, representations),
+            response => return response,
+        };
+
+        let accept = Accept::from_request(request);
+        let offered = representations
+            .iter()
+            .map(|(content_type, _)| *content_type)
+            .collect::<Vec<&str>>();
+        let content_type = match accept.negotiate(&offered) {
+            Some(content_type) => content_type,
+            None => return Response::Empty(Status::NOT_ACCEPTABLE),
+        };
+        let body = representations
+            .into_iter()
+            .find(|(t, _)| *t == content_type)
+            .map(|(_, body)| body)
+            .unwrap_or_default();
+
+        let mut resp = HttpResponse::new(HttpBody::from(body));
+        *resp.status_mut() = status;
+        resp.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static(content_type),
+        );
+        resp.headers_mut()
+            .insert(hyper::header::VARY, HeaderValue::from_static("accept"));
+        Response::Custom(resp)
+    }
+}
+
+/// A response body that is streamed to the client. Without a known length,
+/// it's sent with chunked transfer encoding (over HTTP/1.1).
+///
+/// Streamed bodies aren't given a `Content-MD5`, since that would mean
+/// buffering them. The content type defaults to `application/octet-stream`.
+pub struct StreamBody {
+    body: HttpBody,
+    content_type: Option<String>,
+    length: Option<u64>,
+}
+
+impl StreamBody {
+    /// Stream chunks of bytes. An error ends the response early (aborting
+    /// the connection).
+    pub fn new<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Self {
+            body: HttpBody::wrap_stream(stream.map_err(Into::into)),
+            content_type: None,
+            length: None,
+        }
+    }
+
+    /// Stream the contents of a reader (e.g. a `tokio::fs::File`)
+    pub fn from_reader<R>(reader: R) -> Self
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        Self::new(tokio_util::io::ReaderStream::new(reader))
+    }
+
+    /// Set the `Content-Type`
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(String::from(content_type));
+        self
+    }
+
+    /// Set the `Content-Length`, if it's known ahead of time. The stream must
+    /// produce exactly this many bytes.
+    pub fn length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -174,9 +393,28 @@ impl std::fmt::Display for ResponseError {
     }
 }
 
+/// Drop the body of a response (e.g. to answer a `HEAD` request), keeping its
+/// headers. If the body's length is known, it is kept as the
+/// `Content-Length`.
+pub(crate) fn without_body(response: HttpResponse) -> HttpResponse {
+    let (mut parts, body) = response.into_parts();
+    let has_length = !(parts.status.is_informational()
+        || parts.status == Status::NO_CONTENT
+        || parts.status == Status::NOT_MODIFIED);
+    if has_length && !parts.headers.contains_key(hyper::header::CONTENT_LENGTH) {
+        if let Some(length) = hyper::body::HttpBody::size_hint(&body).exact() {
+            parts
+                .headers
+                .insert(hyper::header::CONTENT_LENGTH, HeaderValue::from(length));
+        }
+    }
+    HttpResponse::from_parts(parts, HttpBody::empty())
+}
+
 const CONTENT_TYPE_TEXT: &str = "text/plain";
 const CONTENT_TYPE_BYTES: &str = "application/octet-stream";
 const CONTENT_TYPE_JSON: &str = "application/json";
+const CONTENT_TYPE_FORM: &str = "application/x-www-form-urlencoded";
 
 impl From<Response> for HttpResponse {
     fn from(value: Response) -> Self {
@@ -200,7 +438,9 @@ impl From<Response> for HttpResponse {
                         hyper::header::CONTENT_TYPE,
                         HeaderValue::from_static(CONTENT_TYPE_BYTES),
                     )
-                    .header(hyper::header::CONTENT_LENGTH, body.len());
+                    .header(hyper::header::CONTENT_LENGTH, body.len())
+                    // Answered by the router (see `Router::range_requests`)
+                    .extension(crate::range::AcceptsRanges);
 
                 if let Some(md5) = md5 {
                     match hyper::header::HeaderValue::from_str(&md5) {
@@ -224,6 +464,35 @@ impl From<Response> for HttpResponse {
                 )
                 .body(hyper::Body::from(json)),
 
+            // Outside of a router, there's no request to negotiate with, so
+            // use the preferred representation. Without any, nothing is
+            // acceptable (like when negotiating).
+            Response::Negotiated(status, representations) => {
+                match representations.into_iter().next() {
+                    Some((content_type, body)) => hyper::Response::builder()
+                        .status(status)
+                        .header(
+                            hyper::header::CONTENT_TYPE,
+                            HeaderValue::from_static(content_type),
+                        )
+                        .body(hyper::Body::from(body)),
+                    None => hyper::Response::builder()
+                        .status(Status::NOT_ACCEPTABLE)
+                        .body(hyper::Body::empty()),
+                }
+            }
+
+            Response::Stream(status, stream) => {
+                let content_type = stream.content_type.as_deref().unwrap_or(CONTENT_TYPE_BYTES);
+                let mut builder = hyper::Response::builder()
+                    .status(status)
+                    .header(hyper::header::CONTENT_TYPE, content_type);
+                if let Some(length) = stream.length {
+                    builder = builder.header(hyper::header::CONTENT_LENGTH, length);
+                }
+                builder.body(stream.body)
+            }
+
             Response::Custom(resp) => Ok(resp),
         }
         .unwrap_or_else(|e| {
@@ -234,3 +503,46 @@ impl From<Response> for HttpResponse {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stream_responses_work() {
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from("id,name\n")),
+            Ok(Bytes::from("1,John\n")),
+        ];
+        let resp = HttpResponse::from(respond::stream(
+            Status::OK,
+            futures_util::stream::iter(chunks),
+        ));
+        assert_eq!(
+            CONTENT_TYPE_BYTES,
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+        assert!(!resp.headers().contains_key(hyper::header::CONTENT_LENGTH));
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("id,name\n1,John\n", body);
+
+        let body = StreamBody::from_reader(&b"hello"[..])
+            .content_type("text/plain")
+            .length(5);
+        let resp = HttpResponse::from(Response::Stream(Status::OK, body));
+        assert_eq!("text/plain", resp.headers()[hyper::header::CONTENT_TYPE]);
+        assert_eq!("5", resp.headers()[hyper::header::CONTENT_LENGTH]);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("hello", body);
+    }
+
+    #[test]
+    fn negotiated_responses_without_representations_work() {
+        let resp = HttpResponse::from(Response::Negotiated(Status::OK, Vec::new()));
+        assert_eq!(Status::NOT_ACCEPTABLE, resp.status());
+
+        let request = HttpRequest::new(HttpBody::empty());
+        let response = Response::Negotiated(Status::OK, Vec::new()).negotiate(&request);
+        assert_eq!(Status::NOT_ACCEPTABLE, response.status());
+    }
+}
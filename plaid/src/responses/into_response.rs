@@ -0,0 +1,210 @@
+use std::convert::TryInto;
+
+use hyper::header::{HeaderName, HeaderValue};
+
+use super::{respond, Response};
+use crate::prelude::*;
+
+/// # Into Response
+///
+/// Types that handlers can return instead of building a [`Response`]. The
+/// `Handler` impl for closures and the `#[handler]` macro accept any
+/// `Result<T, Err>` where `T: IntoResponse`:
+///
+/// ```ignore
+/// #[handler]
+/// async fn get_user(
+///     ctx: &mut RequestContext<Ctx, ()>,
+///     params: RouteParameters,
+/// ) -> Result<Option<Json<User>>, ApiError> {
+///     let user = ctx.global.db.find_user(params.first_i32()).await?;
+///     Ok(user.map(Json))
+/// }
+/// ```
+///
+/// Strings are sent as text, byte vectors as `application/octet-stream`,
+/// [`Json`] as JSON and `None` as a 404. A status or headers can be added in a
+/// tuple, like `(Status::CREATED, Json(user))` or
+/// `([("cache-control", "no-store")], "hello")`.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> Response {
+        Response::Custom(self)
+    }
+}
+
+impl IntoResponse for Status {
+    fn into_response(self) -> Response {
+        Response::Empty(self)
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::Text(Status::OK, self)
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        Response::Text(Status::OK, String::from(self))
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::Bytes {
+            status: Status::OK,
+            body: self,
+            md5: None,
+        }
+    }
+}
+
+/// A body serialized as JSON. Bodies that fail to serialize are answered with
+/// a 500.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json<T>(pub T);
+
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        match respond::json(Status::OK, &self.0) {
+            Ok(response) => response,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Failed to build json response: {}", e);
+                respond::error()
+            }
+        }
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Option<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Some(value) => value.into_response(),
+            None => respond::not_found(),
+        }
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (Status, T) {
+    fn into_response(self) -> Response {
+        let (status, value) = self;
+        value.into_response().with_status(status)
+    }
+}
+
+/// Headers are set on the response, replacing any it already has. Like
+/// [`Response::with_etag`], the response is converted to a `Custom` one.
+/// Invalid header names or values are answered with a 500.
+impl<K, V, T, const N: usize> IntoResponse for ([(K, V); N], T)
+where
+    K: TryInto<HeaderName>,
+    K::Error: std::fmt::Display,
+    V: TryInto<HeaderValue>,
+    V::Error: std::fmt::Display,
+    T: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        let (headers, value) = self;
+        let mut resp = HttpResponse::from(value.into_response());
+        for (name, value) in headers {
+            match (name.try_into(), value.try_into()) {
+                (Ok(name), Ok(value)) => {
+                    resp.headers_mut().insert(name, value);
+                }
+                (Err(e), _) => return invalid_header(e),
+                (_, Err(e)) => return invalid_header(e),
+            }
+        }
+        Response::Custom(resp)
+    }
+}
+
+impl<K, V, T, const N: usize> IntoResponse for (Status, [(K, V); N], T)
+where
+    K: TryInto<HeaderName>,
+    K::Error: std::fmt::Display,
+    V: TryInto<HeaderValue>,
+    V::Error: std::fmt::Display,
+    T: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        let (status, headers, value) = self;
+        (headers, value).into_response().with_status(status)
+    }
+}
+
+#[allow(unused_variables)]
+fn invalid_header(e: impl std::fmt::Display) -> Response {
+    #[cfg(feature = "tracing")]
+    tracing::error!("Invalid response header: {}", e);
+    respond::error()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parts(value: impl IntoResponse) -> (Status, hyper::HeaderMap, String) {
+        let resp = HttpResponse::from(value.into_response());
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn conversions_work() {
+        #[derive(serde::Serialize)]
+        struct User {
+            id: i32,
+        }
+
+        let (status, headers, body) = parts((Status::CREATED, Json(User { id: 1 }))).await;
+        assert_eq!(Status::CREATED, status);
+        assert_eq!("application/json", headers[hyper::header::CONTENT_TYPE]);
+        assert_eq!(r#"{"id":1}"#, body);
+
+        let (status, _, body) = parts("hello").await;
+        assert_eq!((Status::OK, "hello"), (status, body.as_str()));
+
+        let (status, _, _) = parts(None::<String>).await;
+        assert_eq!(Status::NOT_FOUND, status);
+
+        let (status, _, _) = parts(Err::<String, _>(Status::CONFLICT)).await;
+        assert_eq!(Status::CONFLICT, status);
+
+        let (status, headers, body) = parts((
+            Status::ACCEPTED,
+            [("cache-control", "no-store")],
+            vec![1u8, 2],
+        ))
+        .await;
+        assert_eq!(Status::ACCEPTED, status);
+        assert_eq!("no-store", headers[hyper::header::CACHE_CONTROL]);
+        assert_eq!("\u{1}\u{2}", body);
+
+        let (status, _, _) = parts(([("bad header", "x")], "hello")).await;
+        assert_eq!(Status::INTERNAL_SERVER_ERROR, status);
+    }
+}
@@ -0,0 +1,202 @@
+use hyper::header::HeaderValue;
+
+use super::Response;
+use crate::prelude::*;
+
+/// A problem details body (RFC 7807), describing an error in a standard
+/// `application/problem+json` format:
+///
+/// ```ignore
+/// Problem::new(Status::FORBIDDEN)
+///     .type_uri("https://example.com/probs/out-of-credit")
+///     .detail("Your current balance is 30, but that costs 50")
+///     .instance("/account/12345/msgs/abc")
+///     .extension("balance", 30)
+/// ```
+///
+/// The title defaults to the status's reason phrase, and the type to
+/// `about:blank`. Extension members named like the standard members (`type`,
+/// `title`, `status`, `detail` and `instance`) are left out, even if that
+/// member isn't set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    status: Status,
+    type_uri: Option<String>,
+    title: Option<String>,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Problem {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            type_uri: None,
+            title: None,
+            detail: None,
+            instance: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// A URI identifying the type of problem
+    pub fn type_uri(mut self, type_uri: &str) -> Self {
+        self.type_uri = Some(String::from(type_uri));
+        self
+    }
+
+    /// A short summary of the type of problem, which shouldn't change between
+    /// occurrences
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(String::from(title));
+        self
+    }
+
+    /// An explanation of this occurrence of the problem
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(String::from(detail));
+        self
+    }
+
+    /// A URI identifying this occurrence of the problem
+    pub fn instance(mut self, instance: &str) -> Self {
+        self.instance = Some(String::from(instance));
+        self
+    }
+
+    /// Add an extension member, like `balance` or `errors`
+    pub fn extension(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.extensions.insert(String::from(name), value.into());
+        self
+    }
+
+    /// The problem as a JSON object
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut object = self
+            .extensions
+            .iter()
+            .filter(|(name, _)| !STANDARD_MEMBERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<serde_json::Map<_, _>>();
+        let type_uri = self.type_uri.as_deref().unwrap_or("about:blank");
+        object.insert(String::from("type"), type_uri.into());
+        let title = self
+            .title
+            .as_deref()
+            .or_else(|| self.status.canonical_reason());
+        if let Some(title) = title {
+            object.insert(String::from("title"), title.into());
+        }
+        object.insert(String::from("status"), self.status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            object.insert(String::from("detail"), detail.as_str().into());
+        }
+        if let Some(instance) = &self.instance {
+            object.insert(String::from("instance"), instance.as_str().into());
+        }
+        serde_json::Value::Object(object)
+    }
+}
+
+impl From<Problem> for Response {
+    fn from(problem: Problem) -> Self {
+        let mut resp = HttpResponse::new(HttpBody::from(problem.to_json().to_string()));
+        *resp.status_mut() = problem.status;
+        resp.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static(CONTENT_TYPE_PROBLEM),
+        );
+        Response::Custom(resp)
+    }
+}
+
+impl super::IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        Response::from(self)
+    }
+}
+
+/// # Into Problem
+///
+/// Errors that can describe themselves as a [`Problem`]. Routers for handlers
+/// returning these errors can answer them with problem details responses,
+/// instead of a hand-written error handler (see `Router::problem_errors`):
+///
+/// ```ignore
+/// enum ApiError {
+///     NotFound(i32),
+///     Database(sqlx::Error),
+/// }
+///
+/// impl IntoProblem for ApiError {
+///     fn into_problem(self) -> Problem {
+///         match self {
+///             ApiError::NotFound(id) => Problem::new(Status::NOT_FOUND)
+///                 .detail(&format!("No user with id {}", id)),
+///             ApiError::Database(_) => Problem::new(Status::INTERNAL_SERVER_ERROR),
+///         }
+///     }
+/// }
+///
+/// let router: Router<Ctx, (), ApiError> = Router::new().problem_errors();
+/// ```
+pub trait IntoProblem {
+    fn into_problem(self) -> Problem;
+}
+
+impl IntoProblem for Problem {
+    fn into_problem(self) -> Problem {
+        self
+    }
+}
+
+const CONTENT_TYPE_PROBLEM: &str = "application/problem+json";
+
+/// Members defined by RFC 7807, which extensions can't use
+const STANDARD_MEMBERS: [&str; 5] = ["type", "title", "status", "detail", "instance"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problems_serialize() {
+        let problem = Problem::new(Status::FORBIDDEN)
+            .type_uri("https://example.com/probs/out-of-credit")
+            .detail("Your current balance is 30, but that costs 50")
+            .extension("balance", 30)
+            .extension("status", "ignored");
+        assert_eq!(
+            serde_json::json!({
+                "type": "https://example.com/probs/out-of-credit",
+                "title": "Forbidden",
+                "status": 403,
+                "detail": "Your current balance is 30, but that costs 50",
+                "balance": 30,
+            }),
+            problem.to_json()
+        );
+
+        // Even unset standard members can't be extensions
+        let problem = Problem::new(Status::from_u16(599).unwrap())
+            .extension("title", "ignored")
+            .extension("detail", "ignored")
+            .extension("instance", "ignored");
+        assert_eq!(
+            serde_json::json!({"type": "about:blank", "status": 599}),
+            problem.to_json()
+        );
+
+        let resp = HttpResponse::from(Response::from(Problem::new(Status::NOT_FOUND)));
+        assert_eq!(Status::NOT_FOUND, resp.status());
+        assert_eq!(
+            CONTENT_TYPE_PROBLEM,
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+    }
+}
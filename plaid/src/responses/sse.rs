@@ -0,0 +1,207 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt};
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+
+use super::{Response, ResponseError, StreamBody};
+use crate::prelude::*;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type EventStream = Pin<Box<dyn Stream<Item = Result<Event, BoxError>> + Send>>;
+
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A server-sent event. Fields that are never set are left out of the event.
+///
+/// ```ignore
+/// let event = Event::new()
+///     .id("42")
+///     .event("progress")
+///     .json_data(&Progress { done: 3, total: 10 })?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the event's id, which the client sends back in `Last-Event-ID`
+    /// when it reconnects. Line breaks are removed.
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(single_line(id));
+        self
+    }
+
+    /// Set the event's name (the client's listener type). Line breaks are
+    /// removed.
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(single_line(event));
+        self
+    }
+
+    /// Set the event's data. Data with several lines is sent as several
+    /// `data` fields, which the client joins back together.
+    pub fn data(mut self, data: &str) -> Self {
+        self.data = Some(String::from(data));
+        self
+    }
+
+    /// Set the event's data to some value serialized as JSON
+    pub fn json_data<T: serde::Serialize>(mut self, data: &T) -> Result<Self, ResponseError> {
+        self.data = Some(serde_json::to_string(data).map_err(ResponseError::SerializeJson)?);
+        Ok(self)
+    }
+
+    /// Set how long the client should wait before reconnecting
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Add a comment, which clients ignore
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(String::from(comment));
+        self
+    }
+
+    /// Encode the event in the `text/event-stream` format
+    fn encode(&self) -> String {
+        let mut encoded = String::new();
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                encoded.push_str(&format!(":{}\n", line));
+            }
+        }
+        if let Some(event) = &self.event {
+            encoded.push_str(&format!("event: {}\n", event));
+        }
+        if let Some(data) = &self.data {
+            // Split on any line break, keeping empty lines
+            for line in data.split('\n').map(|line| line.trim_end_matches('\r')) {
+                encoded.push_str(&format!("data: {}\n", line));
+            }
+        }
+        if let Some(id) = &self.id {
+            encoded.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(retry) = self.retry {
+            encoded.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        encoded.push('\n');
+        encoded
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value.chars().filter(|c| *c != '\r' && *c != '\n').collect()
+}
+
+/// A `text/event-stream` response, sending events as they're produced by a
+/// stream. While the stream is idle, a comment is sent every so often (15
+/// seconds by default) to keep the connection from being closed by proxies.
+///
+/// An error from the stream ends the response.
+pub struct Sse {
+    events: EventStream,
+    keep_alive: Option<Duration>,
+}
+
+impl Sse {
+    pub fn new<S, E>(events: S) -> Self
+    where
+        S: Stream<Item = Result<Event, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self {
+            events: Box::pin(events.map(|event| event.map_err(Into::into))),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        }
+    }
+
+    /// How long the stream can be idle before a keep-alive comment is sent,
+    /// or `None` to never send them
+    pub fn keep_alive(mut self, interval: Option<Duration>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+}
+
+impl From<Sse> for Response {
+    fn from(sse: Sse) -> Self {
+        let keep_alive = sse.keep_alive;
+        let body = futures_util::stream::unfold(sse.events, move |mut events| async move {
+            let next = match keep_alive {
+                Some(interval) => match tokio::time::timeout(interval, events.next()).await {
+                    Ok(next) => next,
+                    Err(_) => return Some((Ok(Bytes::from_static(b":\n\n")), events)),
+                },
+                None => events.next().await,
+            };
+            next.map(|event| (event.map(|event| Bytes::from(event.encode())), events))
+        });
+
+        let mut resp = HttpResponse::from(Response::Stream(
+            Status::OK,
+            StreamBody::new(body).content_type(CONTENT_TYPE_EVENT_STREAM),
+        ));
+        let headers = resp.headers_mut();
+        headers.insert(
+            hyper::header::CACHE_CONTROL,
+            HeaderValue::from_static("no-cache"),
+        );
+        // Stop proxies like nginx from buffering the events
+        headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+        Response::Custom(resp)
+    }
+}
+
+const CONTENT_TYPE_EVENT_STREAM: &str = "text/event-stream";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::HttpBody as _;
+
+    #[test]
+    fn events_encode() {
+        let event = Event::new()
+            .id("4\n2")
+            .event("update")
+            .data("first\nsecond\r\n")
+            .retry(Duration::from_secs(3))
+            .comment("hi");
+        assert_eq!(
+            ":hi\nevent: update\ndata: first\ndata: second\ndata: \nid: 42\nretry: 3000\n\n",
+            event.encode()
+        );
+        assert_eq!("\n", Event::new().encode());
+    }
+
+    #[tokio::test]
+    async fn sse_responses_work() {
+        let events =
+            futures_util::stream::iter(vec![Ok::<_, std::io::Error>(Event::new().data("hello"))])
+                .chain(futures_util::stream::pending());
+        let sse = Sse::new(events).keep_alive(Some(Duration::from_millis(10)));
+        let resp = HttpResponse::from(Response::from(sse));
+        assert_eq!(
+            CONTENT_TYPE_EVENT_STREAM,
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+        assert_eq!("no-cache", resp.headers()[hyper::header::CACHE_CONTROL]);
+
+        let mut body = resp.into_body();
+        assert_eq!("data: hello\n\n", body.data().await.unwrap().unwrap());
+        assert_eq!(":\n\n", body.data().await.unwrap().unwrap());
+    }
+}
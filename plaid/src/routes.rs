@@ -1,10 +1,29 @@
+mod guards;
+mod layers;
 mod macros;
+mod normalize;
+mod options;
+mod parameters;
 mod tree;
 
 use std::sync::Arc;
 
 use crate::{handlers, prelude::*};
-pub use tree::{Parameter, RouteParameters, RouteTree};
+use layers::Layer;
+use normalize::Normalization;
+pub use normalize::{DotSegments, EncodedSlashes};
+pub use options::{RouteOptions, TrailingSlash};
+pub use parameters::{
+    FromParam, Parameter, ParameterError, ParameterKey, RouteMatch, RouteParameters,
+};
+pub use tree::{RouteError, RouteTree};
+
+type ErrorHandler<GlobalCtx, LocalCtx, Err> =
+    Arc<dyn Fn(&mut RequestContext<GlobalCtx, LocalCtx>, Err) -> Response + Send + Sync>;
+type NotFoundHandler<GlobalCtx, LocalCtx> =
+    Arc<dyn Fn(&mut RequestContext<GlobalCtx, LocalCtx>) -> Response + Send + Sync>;
+type MethodNotAllowedHandler<GlobalCtx, LocalCtx> =
+    Arc<dyn Fn(&mut RequestContext<GlobalCtx, LocalCtx>, &[Method]) -> Response + Send + Sync>;
 
 // TODO: Document options
 pub struct Router<GlobalCtx, LocalCtx, Err>
@@ -13,10 +32,19 @@ where
     LocalCtx: 'static,
 {
     pub(crate) tree: RouteTree<GlobalCtx, LocalCtx, Err>,
-    pub(crate) redirect_trailing: bool,
+    pub(crate) trailing_slash: TrailingSlash,
+    pub(crate) normalization: Normalization,
+    pub(crate) case_insensitive: bool,
     pub(crate) handle_options: bool, // TODO: Note this is not CORS, and will not populate CORS headers use the middleware instead
-    pub(crate) error_handler:
-        Arc<dyn Fn(&mut RequestContext<GlobalCtx, LocalCtx>, Err) -> Response + Send + Sync>,
+    pub(crate) handle_head: bool,
+    pub(crate) conditional_requests: bool,
+    pub(crate) auto_etag: bool,
+    pub(crate) range_requests: bool,
+    pub(crate) body_limit: Option<usize>,
+    pub(crate) error_handler: ErrorHandler<GlobalCtx, LocalCtx, Err>,
+    pub(crate) not_found_handler: NotFoundHandler<GlobalCtx, LocalCtx>,
+    pub(crate) method_not_allowed_handler: MethodNotAllowedHandler<GlobalCtx, LocalCtx>,
+    pub(crate) layers: Vec<Layer<GlobalCtx, LocalCtx>>,
 }
 
 impl<G, L, E> Default for Router<G, L, E>
@@ -27,17 +55,33 @@ where
         Self {
             tree: RouteTree::new(),
             // cors: false,
-            redirect_trailing: false,
+            trailing_slash: TrailingSlash::default(),
+            normalization: Normalization::default(),
+            case_insensitive: false,
             handle_options: true,
+            handle_head: true,
+            conditional_requests: true,
+            auto_etag: false,
+            range_requests: true,
+            body_limit: None,
             error_handler: Arc::new(handlers::default_error_handler),
+            not_found_handler: Arc::new(handlers::default_not_found_handler),
+            method_not_allowed_handler: Arc::new(handlers::default_method_not_allowed_handler),
+            layers: Vec::new(),
         }
     }
 }
 
-impl<'ctx, G, L, E> Router<G, L, E>
+/// Prints the router's route tree (see [`Router::route_table`] for a flat
+/// listing)
+impl<G, L, E> std::fmt::Debug for Router<G, L, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.tree)
+    }
+}
+
+impl<G, L, E> Router<G, L, E>
 where
-    G: 'ctx,
-    L: 'ctx,
     E: Send + Sync + 'static,
 {
     pub fn new() -> Self {
@@ -49,8 +93,50 @@ where
     //     self
     // }
 
-    pub fn redirect_trailing_slash(mut self, opt: bool) -> Self {
-        self.redirect_trailing = opt;
+    /// Shortcut for [`Router::trailing_slash`], redirecting to the canonical
+    /// form of a route with a 308 (`true`) or only matching it exactly
+    /// (`false`)
+    pub fn redirect_trailing_slash(self, opt: bool) -> Self {
+        self.trailing_slash(if opt {
+            TrailingSlash::Redirect(Status::PERMANENT_REDIRECT)
+        } else {
+            TrailingSlash::Strict
+        })
+    }
+
+    /// How to handle requests that only differ from a route by a trailing
+    /// slash (see [`TrailingSlash`]). Routes can override this with
+    /// [`RouteOptions::trailing_slash`]. Defaults to `TrailingSlash::Strict`.
+    pub fn trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = policy;
+        self
+    }
+
+    /// Treat runs of slashes in request paths (`/a//b`) as a single slash.
+    /// Defaults to `true`.
+    pub fn collapse_slashes(mut self, opt: bool) -> Self {
+        self.normalization.collapse_slashes = opt;
+        self
+    }
+
+    /// How to handle `.` and `..` segments in request paths (see
+    /// [`DotSegments`]). Defaults to `DotSegments::Resolve`.
+    pub fn dot_segments(mut self, policy: DotSegments) -> Self {
+        self.normalization.dot_segments = policy;
+        self
+    }
+
+    /// How to handle percent-encoded slashes in request paths (see
+    /// [`EncodedSlashes`]). Defaults to `EncodedSlashes::Decode`.
+    pub fn encoded_slashes(mut self, policy: EncodedSlashes) -> Self {
+        self.normalization.encoded_slashes = policy;
+        self
+    }
+
+    /// Match static route segments ignoring ASCII case, so `/Users` is routed
+    /// like `/users`. Parameter values keep their case. Defaults to `false`.
+    pub fn case_insensitive(mut self, opt: bool) -> Self {
+        self.case_insensitive = opt;
         self
     }
 
@@ -59,6 +145,79 @@ where
         self
     }
 
+    /// Answer `HEAD` requests for routes without a `HEAD` handler by running
+    /// their `GET` handler and dropping the response body. The `GET`
+    /// response's headers (including `Content-Length`) are kept. Defaults to
+    /// `true`.
+    pub fn handle_head(mut self, opt: bool) -> Self {
+        self.handle_head = opt;
+        self
+    }
+
+    /// Answer conditional `GET` and `HEAD` requests (`If-None-Match`,
+    /// `If-Modified-Since`, etc.) from the `ETag` and `Last-Modified` headers
+    /// on handlers' `200` responses, with a 304 or 412 instead of the body.
+    /// Defaults to `true`.
+    ///
+    /// Since this happens after the handler has run, other methods (like
+    /// `PUT`) are left alone, and their handlers should check `If-Match`
+    /// themselves before making changes, with `RequestContext::preconditions`.
+    pub fn conditional_requests(mut self, opt: bool) -> Self {
+        self.conditional_requests = opt;
+        self
+    }
+
+    /// Add a strong `ETag` (hashed from the body) to `GET` responses that
+    /// don't set one, so they can be answered with a 304 by
+    /// [`Router::conditional_requests`]. Streamed bodies are skipped. Defaults
+    /// to `false`.
+    pub fn auto_etag(mut self, opt: bool) -> Self {
+        self.auto_etag = opt;
+        self
+    }
+
+    /// Answer `Range` requests for handlers' buffered `200` responses that
+    /// allow them (`Response::Bytes`, or any with `Accept-Ranges: bytes`),
+    /// with a 206 of the requested ranges or a 416 if none are satisfiable.
+    /// Those responses advertise `Accept-Ranges: bytes` only while this is
+    /// on. Defaults to `true`.
+    ///
+    /// Large bodies should be served from a seekable source with
+    /// [`range::serve`](crate::range::serve) instead, so they aren't
+    /// buffered.
+    pub fn range_requests(mut self, opt: bool) -> Self {
+        self.range_requests = opt;
+        self
+    }
+
+    /// The most bytes a request body may have, replacing the server's limit
+    /// (see `Server::body_limit`). Routes can have their own limits with
+    /// `RouteOptions::body_limit`, and a nested router's limit applies to the
+    /// routes nested from it.
+    ///
+    /// Requests with a `Content-Length` over the limit are answered with
+    /// `respond::payload_too_large` without calling the handler. Otherwise,
+    /// `RequestContext::body` (and `body_json`) stop reading once the body is
+    /// over the limit, and the handler's response is replaced with the 413
+    /// (unless it's already a 413, like a `JsonError` problem).
+    pub fn body_limit(mut self, max_bytes: usize) -> Self {
+        self.body_limit = Some(max_bytes);
+        self
+    }
+
+    /// Register a [`FromParam`] type for route parameters hinted with
+    /// `:name{hint}`. This must be called before adding any routes that use
+    /// the hint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hint is one of the built-in `String`, `u32` or `i32`
+    /// hints
+    pub fn parameter_type<T: FromParam>(mut self, hint: &str) -> Self {
+        self.tree.register_parameter_type::<T>(hint);
+        self
+    }
+
     pub fn on_error(
         mut self,
         handler: impl Fn(&mut RequestContext<G, L>, E) -> Response + Send + Sync + 'static,
@@ -67,48 +226,333 @@ where
         self
     }
 
-    pub fn add<F: Handler<G, L, E>>(
+    /// Answer errors from handlers with their problem details (see
+    /// [`IntoProblem`]), as an `application/problem+json` body
+    pub fn problem_errors(mut self) -> Self
+    where
+        E: IntoProblem,
+    {
+        self.error_handler = Arc::new(handlers::problem_error_handler);
+        self
+    }
+
+    /// Build the response for requests that don't match any route. Defaults to
+    /// an empty 404. Only used by the router the request is given to, not by
+    /// routers nested in it (see [`Router::nest`]).
+    pub fn on_not_found(
+        mut self,
+        handler: impl Fn(&mut RequestContext<G, L>) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.not_found_handler = Arc::new(handler);
+        self
+    }
+
+    /// Build the response for requests that match a route, but not any of its
+    /// methods. The handler is given the methods the route does allow, which
+    /// are added as an `Allow` header if the response doesn't set one.
+    /// Defaults to an empty 405. As with [`Router::on_not_found`], only used
+    /// by the outermost router.
+    pub fn on_method_not_allowed(
+        mut self,
+        handler: impl Fn(&mut RequestContext<G, L>, &[Method]) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.method_not_allowed_handler = Arc::new(handler);
+        self
+    }
+
+    /// Add a route to the router.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the route can't be added (see [`Router::try_add`]), so a
+    /// misconfigured router fails at startup.
+    pub fn add<F: Handler<G, L, E>>(&mut self, methods: Vec<Method>, path: &str, handler: F) {
+        self.add_wrapped(methods, path, crate::handlers::wrapped(handler))
+    }
+
+    /// Add a route to the router, returning a [`RouteError`] if the path is
+    /// malformed or conflicts with an existing route.
+    pub fn try_add<F: Handler<G, L, E>>(
         &mut self,
         methods: Vec<Method>,
-        path: &'static str,
+        path: &str,
         handler: F,
-    ) {
-        self.tree
-            .add_route(&methods, path, crate::handlers::wrapped(handler))
+    ) -> Result<(), RouteError> {
+        self.try_add_with_options(methods, path, RouteOptions::default(), handler)
     }
 
+    /// Add an already wrapped handler. Panics like [`Router::add`].
     pub fn add_wrapped(
         &mut self,
         methods: Vec<Method>,
-        path: &'static str,
+        path: &str,
         handler: WrappedHandler<G, L, E>,
     ) {
-        self.tree.add_route(&methods, path, handler);
+        if let Err(e) = self
+            .tree
+            .add_route(&methods, path, handler, &RouteOptions::default())
+        {
+            panic!("{}", e);
+        }
+    }
+
+    /// Add a route with [`RouteOptions`] that override the router's. Panics
+    /// like [`Router::add`].
+    pub fn add_with_options<F: Handler<G, L, E>>(
+        &mut self,
+        methods: Vec<Method>,
+        path: &str,
+        options: RouteOptions,
+        handler: F,
+    ) {
+        if let Err(e) = self.try_add_with_options(methods, path, options, handler) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Add a route with [`RouteOptions`], returning a [`RouteError`] like
+    /// [`Router::try_add`]
+    pub fn try_add_with_options<F: Handler<G, L, E>>(
+        &mut self,
+        methods: Vec<Method>,
+        path: &str,
+        options: RouteOptions,
+        handler: F,
+    ) -> Result<(), RouteError> {
+        self.tree
+            .add_route(&methods, path, crate::handlers::wrapped(handler), &options)
+    }
+
+    /// Every route added to the router, sorted by template, with the methods
+    /// it handles. Nested routers' routes are included under their prefix.
+    pub fn routes(&self) -> impl Iterator<Item = (&str, Vec<Method>)> {
+        self.tree.routes()
+    }
+
+    /// Render the router's routes as a table, one route per line, e.g. to
+    /// print at startup:
+    ///
+    /// ```txt
+    /// METHODS    ROUTE
+    /// GET        /
+    /// GET, POST  /users
+    /// GET        /users/:id{i32}
+    /// ```
+    pub fn route_table(&self) -> String {
+        let rows = self
+            .routes()
+            .map(|(template, methods)| (tree::join_methods(&methods), template))
+            .collect::<Vec<_>>();
+        let width = rows
+            .iter()
+            .map(|(methods, _)| methods.len())
+            .chain(std::iter::once("METHODS".len()))
+            .max()
+            .unwrap_or_default();
+
+        let mut table = format!("{:width$}  ROUTE\n", "METHODS", width = width);
+        for (methods, template) in rows {
+            table.push_str(&format!(
+                "{:width$}  {}\n",
+                methods,
+                template,
+                width = width
+            ));
+        }
+        table
     }
 
+    /// Route a path and method, as if requested with no headers
+    #[cfg(test)]
     pub(crate) fn route(&self, path: &str, method: &Method) -> RouterResult<G, L, E> {
-        let path = if self.redirect_trailing {
-            path.trim_matches('/')
-        } else {
-            path.trim_start_matches('/')
+        let path = format!("/{}", path.trim_start_matches('/'));
+        match hyper::Request::builder()
+            .method(method)
+            .uri(path)
+            .body(HttpBody::empty())
+        {
+            Ok(request) => self.route_request(&request),
+            Err(_) => RouterResult::InvalidPath,
+        }
+    }
+
+    pub(crate) fn route_request(&self, request: &HttpRequest) -> RouterResult<G, L, E> {
+        let method = request.method();
+
+        // Decode and normalize the path before matching its segments
+        let normalized = match self.normalization.normalize(request.uri().path()) {
+            Some(normalized) => normalized,
+            None => return RouterResult::InvalidPath,
         };
+        let parts = normalized
+            .segments
+            .iter()
+            .map(|segment| segment.as_ref())
+            .collect::<Vec<&str>>();
 
         // Find the route
-        if let Some((mmap, params)) = self.tree.route_to(path) {
+        if let Some((leaf, mut params)) = self.tree.route_to(&parts, self.case_insensitive, request)
+        {
+            // Check the path against the route's canonical form
+            if normalized.trailing_slash != leaf.has_trailing_slash() {
+                match leaf.trailing_slash().unwrap_or(self.trailing_slash) {
+                    TrailingSlash::Strict => return RouterResult::PathNotFound,
+                    TrailingSlash::MatchBoth => {}
+                    TrailingSlash::Redirect(status) => {
+                        let location = normalized.to_path(leaf.has_trailing_slash());
+                        return RouterResult::Redirect(status, location);
+                    }
+                }
+            }
+
             // If handling options, return the option list
             if self.handle_options && method == Method::OPTIONS {
-                let mut methods: Vec<Method> = mmap.keys().cloned().collect();
-                methods.push(Method::OPTIONS);
-                RouterResult::Options(methods)
-            } else if let Some(handler) = mmap.get(method) {
-                RouterResult::Found(handler.clone(), params)
+                RouterResult::Options(self.allowed_methods(leaf, request))
+            } else if let Some((endpoint, captures)) =
+                leaf.endpoint(method, request).or_else(|| {
+                    // Fall back to the GET handler for HEAD requests
+                    if self.handle_head && method == Method::HEAD {
+                        leaf.endpoint(&Method::GET, request)
+                    } else {
+                        None
+                    }
+                })
+            {
+                // Add any parameters captured by the guards (e.g. from the
+                // host)
+                for (name, value) in captures {
+                    params.named.insert(name, Parameter::String(value));
+                }
+                RouterResult::Found(
+                    endpoint.handler.clone(),
+                    RouteMatch {
+                        template: String::from(leaf.template()),
+                        path: normalized.to_path(normalized.trailing_slash),
+                        params,
+                        body_limit: endpoint.body_limit,
+                    },
+                )
             } else {
-                RouterResult::MethodNotFound
+                RouterResult::MethodNotFound(self.allowed_methods(leaf, request))
             }
         } else {
             RouterResult::PathNotFound
         }
     }
+
+    /// The methods a route can be requested with (for a request that passes
+    /// their guards), including those the router answers itself
+    fn allowed_methods(&self, leaf: &tree::Leaf<G, L, E>, request: &HttpRequest) -> Vec<Method> {
+        let mut methods = leaf.methods(request);
+        if self.handle_head && methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+            let get = methods.iter().position(|m| m == Method::GET).unwrap_or(0);
+            methods.insert(get + 1, Method::HEAD);
+        }
+        if self.handle_options && !methods.contains(&Method::OPTIONS) {
+            methods.push(Method::OPTIONS);
+        }
+        methods
+    }
+}
+
+impl<G, L, E> Router<G, L, E>
+where
+    G: Send + Sync + 'static,
+    L: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    /// Wrap every route in this router with some middleware. Unlike
+    /// middleware added to the `Server`, layers only apply to requests that
+    /// are routed to this router's handlers, so they can be used for things
+    /// like authentication on a nested `/admin` router.
+    ///
+    /// Layers are applied when the router is nested or given to the server,
+    /// using the router's error handler at that point. A new middleware
+    /// instance is built for each route, hence the `Clone` bound. As with the
+    /// server, the last layer added is the outermost.
+    pub fn layer<M>(mut self, middleware: M) -> Self
+    where
+        M: ToMiddleware<G, L> + Clone + Send + Sync + 'static,
+    {
+        self.layers.push(layers::layer(middleware));
+        self
+    }
+
+    /// Graft the routes of another router under `prefix`, so that a route
+    /// `/users` in `router` is served at `{prefix}/users`.
+    ///
+    /// The nested router's layers and error handler are applied to its
+    /// routes. Its other options (e.g. `handle_options`) are replaced by this
+    /// router's, as are its not found and method not allowed handlers, since
+    /// the nested router doesn't exist once its routes are grafted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the routes can't be added (see [`Router::try_nest`])
+    pub fn nest(&mut self, prefix: &str, router: Router<G, L, E>) {
+        if let Err(e) = self.try_nest(prefix, router) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Graft the routes of another router under `prefix` (see
+    /// [`Router::nest`]), returning a [`RouteError`] if any of them conflict
+    /// with existing routes. No routes are grafted if there's an error.
+    pub fn try_nest(
+        &mut self,
+        prefix: &str,
+        mut router: Router<G, L, E>,
+    ) -> Result<(), RouteError> {
+        router.wrap_handlers();
+        self.tree.merge_parameter_types(&router.tree);
+
+        let prefix = prefix.trim_end_matches('/');
+        let mut routes = Vec::new();
+        for leaf in router.tree.entries() {
+            // The nested router's root is served at the prefix itself
+            let path = match leaf.template() {
+                "/" if !prefix.is_empty() => String::from(prefix),
+                template => format!("{}{}", prefix, template),
+            };
+            for (method, endpoint) in leaf.endpoints() {
+                // The nested router's body limit becomes its routes' own
+                let options = RouteOptions {
+                    guards: endpoint.guards.clone(),
+                    body_limit: endpoint.body_limit.or(router.body_limit),
+                    ..leaf.options()
+                };
+                routes.push((method, path.clone(), endpoint.handler.clone(), options));
+            }
+        }
+
+        // The nested routes can't conflict with each other, so checking them
+        // against the existing ones is enough
+        for (method, path, _, options) in routes.iter() {
+            self.tree
+                .check_route(std::slice::from_ref(*method), path, options)?;
+        }
+        for (method, path, handler, options) in routes {
+            self.tree
+                .add_route(std::slice::from_ref(method), &path, handler, &options)?;
+        }
+        Ok(())
+    }
+
+    /// Wrap every handler in the router's layers (if there are any)
+    pub(crate) fn apply_layers(&mut self) {
+        if !self.layers.is_empty() {
+            self.wrap_handlers();
+        }
+    }
+
+    /// Wrap every handler in the router's layers and error handler, so they
+    /// still apply once the handlers are grafted onto another router
+    fn wrap_handlers(&mut self) {
+        let layers = std::mem::take(&mut self.layers);
+        let error_handler = self.error_handler.clone();
+        self.tree
+            .map_handlers(&mut |handler| layers::layered(handler, &layers, error_handler.clone()));
+    }
 }
 
 #[async_trait]
@@ -120,31 +564,89 @@ where
 {
     async fn call(&self, ctx: &mut RequestContext<G, L>) -> HttpResponse {
         // Process the route
-        let (ctx, result) = {
-            let mut ctx = ctx;
-            let result = match self.route(ctx.request.uri().path(), ctx.request.method()) {
-                RouterResult::Found(handler, params) => handler.handle(&mut ctx, params).await,
-                RouterResult::Options(opts) => Ok(respond::options(&opts)),
-                RouterResult::MethodNotFound => Ok(respond::method_not_allowed()),
-                RouterResult::PathNotFound => Ok(respond::not_found()),
-            };
-            (ctx, result)
+        let result = match self.route_request(&ctx.request) {
+            RouterResult::Found(handler, route) => {
+                if let Some(max_bytes) = route.body_limit.or(self.body_limit) {
+                    crate::limits::set(&mut ctx.request, max_bytes);
+                }
+                // Middleware may have rewritten the request since it was
+                // resolved by the server, so replace the match
+                let params = route.params.clone();
+                ctx.route = Some(route);
+                match crate::limits::exceeded_by_length(&ctx.request) {
+                    Some(max_bytes) => Ok(respond::payload_too_large(max_bytes)),
+                    None => handler.handle(ctx, params).await,
+                }
+            }
+            RouterResult::Options(opts) => Ok(respond::options(&opts)),
+            RouterResult::MethodNotFound(allowed) => {
+                let mut resp = HttpResponse::from((self.method_not_allowed_handler)(ctx, &allowed));
+                if !resp.headers().contains_key(hyper::header::ALLOW) {
+                    resp.headers_mut()
+                        .insert(hyper::header::ALLOW, respond::allow_header(&allowed));
+                }
+                Ok(Response::Custom(resp))
+            }
+            RouterResult::PathNotFound => Ok((self.not_found_handler)(ctx)),
+            RouterResult::InvalidPath => Ok(respond::status(Status::BAD_REQUEST)),
+            RouterResult::Redirect(status, mut location) => {
+                if let Some(query) = ctx.request.uri().query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                Ok(respond::redirect(status, &location))
+            }
         };
 
-        // Borrow checker gets angry that ctx is borrowed twice if we try to
-        // call the error handler on it while "handler" is still in scope,
-        // so defer the error handling until here.
-        let (_ctx, response) = {
-            let mut ctx = ctx;
-            let resp = match result {
-                Ok(response) => response,
-                Err(e) => (self.error_handler)(&mut ctx, e),
-            };
-            (ctx, resp)
+        // Defer the error handling until "handler" is out of scope, so ctx
+        // isn't borrowed twice
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => (self.error_handler)(ctx, e),
+        };
+        // Handlers that already answered with a 413 (like a problem body
+        // from a `JsonError`) keep their response
+        let response = match crate::limits::exceeded(&ctx.request) {
+            Some(max_bytes) if response.status() != Status::PAYLOAD_TOO_LARGE => {
+                respond::payload_too_large(max_bytes)
+            }
+            _ => response,
         };
+        let response = response.negotiate(&ctx.request);
 
         // Convert Response to Hyper
-        HttpResponse::from(response)
+        let mut response = HttpResponse::from(response);
+        if self.conditional_requests {
+            response = crate::conditional::apply(&ctx.request, response, self.auto_etag).await;
+        }
+        if self.range_requests {
+            response = crate::range::apply(&ctx.request, response).await;
+        }
+        if self.handle_head && ctx.request.method() == Method::HEAD {
+            crate::responses::without_body(response)
+        } else {
+            response
+        }
+    }
+}
+
+/// Resolves the route for a request before it is passed through the server's
+/// middleware, so middleware can read the match from the context
+pub(crate) trait Resolver: Send + Sync {
+    fn resolve(&self, request: &HttpRequest) -> Option<RouteMatch>;
+}
+
+impl<G, L, E> Resolver for Router<G, L, E>
+where
+    G: Send + Sync + 'static,
+    L: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    fn resolve(&self, request: &HttpRequest) -> Option<RouteMatch> {
+        match self.route_request(request) {
+            RouterResult::Found(_, route) => Some(route),
+            _ => None,
+        }
     }
 }
 
@@ -153,19 +655,25 @@ where
     G: 'static,
     L: 'static,
 {
-    Found(WrappedHandler<G, L, E>, RouteParameters),
+    Found(WrappedHandler<G, L, E>, RouteMatch),
     PathNotFound,
-    MethodNotFound,
+    MethodNotFound(Vec<Method>),
     Options(Vec<Method>),
+    /// The path is malformed or was rejected while normalizing it
+    InvalidPath,
+    /// The path only differs from the route by a trailing slash. Contains the
+    /// redirect status and the canonical path.
+    Redirect(Status, String),
 }
 
 // TODO: better route search context? Some kind of struct instead of hashmap?
-// TODO: RouterOptions (redirect trailing slash, cors?)
+// TODO: RouterOptions (cors?)
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::testing::{call, context};
 
     #[derive(Debug)]
     enum BasicError {}
@@ -279,14 +787,10 @@ mod tests {
     }
 
     async fn test_route(router: &Router<(), (), BasicError>, path: &str, expect_status: u16) {
-        let mut ctx = RequestContext {
-            global: Arc::new(()),
-            local: (),
-            request: HttpRequest::new(HttpBody::empty()),
-        };
-        if let RouterResult::Found(h, p) = router.route(path, &Method::GET) {
+        let mut ctx = context(HttpRequest::new(HttpBody::empty()));
+        if let RouterResult::Found(h, route) = router.route(path, &Method::GET) {
             let ctx = &mut ctx;
-            match h.handle(ctx, p).await {
+            match h.handle(ctx, route.params).await {
                 Ok(resp) => assert_eq!(resp.status(), Status::from_u16(expect_status).unwrap()),
                 Err(e) => panic!("{:?}", e),
             }
@@ -316,6 +820,469 @@ mod tests {
         test_route(&router, "/abc/striiiiing", 205).await;
     }
 
+    #[tokio::test]
+    async fn router_matches_many_children() {
+        // Enough children that they're kept in a map rather than a vec
+        let mut router = Router::new();
+        for i in 0..20 {
+            router.add(vec![Method::GET], &format!("/abc{}", i), AbcHandler {});
+        }
+        router.add(vec![Method::GET], "/abc5/:id{i32}", AbcIdHandler {});
+
+        test_route(&router, "/abc0", 201).await;
+        test_route(&router, "/abc19", 201).await;
+        test_route(&router, "/abc5/3", 204).await;
+        assert_eq!(21, router.routes().count());
+    }
+
+    #[tokio::test]
+    async fn router_matches_catch_all() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/static/index", AbcHandler {});
+        router.add(vec![Method::GET], "/static/:id{i32}", AbcIdHandler {});
+        router.add(vec![Method::GET], "/static/*path", AbcStrHandler {});
+
+        test_route(&router, "/static/index", 201).await;
+        test_route(&router, "/static/123", 204).await;
+        test_route(&router, "/static/css/site.css", 205).await;
+
+        if let RouterResult::Found(_, RouteMatch { params, .. }) =
+            router.route("/static/css/site.css", &Method::GET)
+        {
+            assert_eq!(
+                Some(String::from("css/site.css")),
+                params.get_string("path")
+            );
+            assert_eq!(1, params.ordered.len());
+        } else {
+            panic!("failed to route to catch-all")
+        }
+    }
+
+    #[tokio::test]
+    async fn router_backtracks() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/abc/def/x", AbcDefHandler {});
+        router.add(vec![Method::GET], "/abc/:id/y", AbcStrHandler {});
+        router.add(vec![Method::GET], "/num/:id{i32}/a", AbcIdHandler {});
+        router.add(vec![Method::GET], "/num/:name/b", AbcStrHandler {});
+        router.add(vec![Method::GET], "/num/:name", AbcGhiHandler {});
+        router.add(vec![Method::GET], "/num/*rest", AbcHandler {});
+
+        test_route(&router, "/abc/def/x", 202).await;
+        test_route(&router, "/abc/def/y", 205).await;
+        test_route(&router, "/num/5/a", 204).await;
+        test_route(&router, "/num/5/b", 205).await;
+        test_route(&router, "/num/5", 203).await;
+        test_route(&router, "/num/5/c", 201).await;
+
+        // Parameters bound on abandoned branches are dropped
+        if let RouterResult::Found(_, RouteMatch { params, .. }) =
+            router.route("/num/5/b", &Method::GET)
+        {
+            assert_eq!(1, params.ordered.len());
+            assert_eq!(Some(String::from("5")), params.get_string("name"));
+            assert!(!params.named.contains_key("id"));
+        } else {
+            panic!("failed to route to /num/:name/b")
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    impl FromParam for Color {
+        fn from_param(param: &str) -> Option<Self> {
+            match param {
+                "red" => Some(Color::Red),
+                "green" => Some(Color::Green),
+                _ => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn router_parses_parameter_types() {
+        let mut router = Router::new().parameter_type::<Color>("Color");
+        router.add(vec![Method::GET], "/color/:color{Color}", AbcHandler {});
+        router.add(vec![Method::GET], "/color/:id{Uuid}", AbcIdHandler {});
+        router.add(vec![Method::GET], "/color/:other", AbcStrHandler {});
+
+        test_route(&router, "/color/red", 201).await;
+        test_route(&router, "/color/936da01f-9abd-4d9d-80c7-02af85c822a8", 204).await;
+        test_route(&router, "/color/blue", 205).await;
+
+        if let RouterResult::Found(_, RouteMatch { params, .. }) =
+            router.route("/color/green", &Method::GET)
+        {
+            let color = params
+                .named
+                .get("color")
+                .and_then(Parameter::parse::<Color>);
+            assert_eq!(Some(Color::Green), color);
+        } else {
+            panic!("failed to route to /color/:color")
+        }
+    }
+
+    #[tokio::test]
+    async fn router_rejects_conflicts() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/abc/:id{i32}", AbcIdHandler {});
+        router.add(vec![Method::GET], "/abc/*rest", AbcHandler {});
+
+        let add = |router: &mut Router<(), (), BasicError>, methods, path| {
+            router.try_add(methods, path, AbcStrHandler {})
+        };
+        assert!(matches!(
+            add(&mut router, vec![Method::PUT, Method::GET], "/abc/:id{i32}"),
+            Err(RouteError::DuplicateRoute { method, .. }) if method == Method::GET
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::GET], "/abc/:num{i32}/x"),
+            Err(RouteError::AmbiguousParameter { .. })
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::GET], "/abc/*other"),
+            Err(RouteError::AmbiguousParameter { .. })
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::GET], "/:a/:a{i32}"),
+            Err(RouteError::DuplicateParameterName { .. })
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::GET], "/*rest/abc"),
+            Err(RouteError::CatchAllNotLast { .. })
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::GET], "/:id{Unknown}"),
+            Err(RouteError::UnknownTypeHint { .. })
+        ));
+        assert!(matches!(
+            add(&mut router, vec![Method::POST], "/abc/:id{i32}/"),
+            Err(RouteError::TrailingSlashConflict { existing, .. }) if existing == "/abc/:id{i32}"
+        ));
+        for path in &["/:id{i32", "/:id{}", "/:id{i32}x", "/:id}", "/*rest{i32}"] {
+            assert!(matches!(
+                add(&mut router, vec![Method::GET], path),
+                Err(RouteError::MalformedTypeHint { .. })
+            ));
+        }
+
+        // Guarded routes conflict with routes with the same guards, in any
+        // order
+        router.add_with_options(
+            vec![Method::GET],
+            "/guarded",
+            RouteOptions::new()
+                .host("example.com")
+                .header("accept-version", "2"),
+            AbcHandler {},
+        );
+        let add_guarded = |router: &mut Router<(), (), BasicError>, options| {
+            router.try_add_with_options(vec![Method::GET], "/guarded", options, AbcStrHandler {})
+        };
+        assert!(matches!(
+            add_guarded(
+                &mut router,
+                RouteOptions::new()
+                    .header("Accept-Version", "2")
+                    .host("Example.com"),
+            ),
+            Err(RouteError::DuplicateRoute { .. })
+        ));
+        assert!(add_guarded(&mut router, RouteOptions::new().host("example.com")).is_ok());
+        assert!(add_guarded(&mut router, RouteOptions::new()).is_ok());
+
+        // Host parameters share the route's named parameters
+        assert!(matches!(
+            router.try_add_with_options(
+                vec![Method::GET],
+                "/tenants/:tenant",
+                RouteOptions::new().host(":tenant.example.com"),
+                AbcStrHandler {},
+            ),
+            Err(RouteError::DuplicateParameterName { name, .. }) if name == "tenant"
+        ));
+        assert!(matches!(
+            router.try_add_with_options(
+                vec![Method::GET],
+                "/tenants",
+                RouteOptions::new().host(":tenant.:tenant.com"),
+                AbcStrHandler {},
+            ),
+            Err(RouteError::DuplicateParameterName { .. })
+        ));
+
+        // Routes for the same path share its trailing slash policy
+        let add_policy = |router: &mut Router<(), (), BasicError>, options| {
+            router.try_add_with_options(vec![Method::POST], "/policy", options, AbcStrHandler {})
+        };
+        router.add_with_options(
+            vec![Method::GET],
+            "/policy",
+            RouteOptions::new().trailing_slash(TrailingSlash::MatchBoth),
+            AbcHandler {},
+        );
+        assert!(matches!(
+            add_policy(
+                &mut router,
+                RouteOptions::new().trailing_slash(TrailingSlash::Strict)
+            ),
+            Err(RouteError::TrailingSlashPolicyConflict { .. })
+        ));
+        assert!(add_policy(&mut router, RouteOptions::new()).is_ok());
+
+        // Failed additions leave the tree unchanged
+        test_route(&router, "/abc/123", 204).await;
+        test_route(&router, "/abc/x/y", 201).await;
+        assert!(matches!(
+            router.route("/abc/123", &Method::PUT),
+            RouterResult::MethodNotFound(_)
+        ));
+    }
+
+    /// Adds its tag to the `x-tag` header of responses
+    #[derive(Clone)]
+    struct Tag(&'static str);
+
+    struct TagMiddleware {
+        tag: &'static str,
+        next: Arc<dyn Middleware<(), ()>>,
+    }
+
+    impl ToMiddleware<(), ()> for Tag {
+        fn wrap(self, next: Arc<dyn Middleware<(), ()>>) -> Arc<dyn Middleware<(), ()>> {
+            Arc::new(TagMiddleware { tag: self.0, next })
+        }
+    }
+
+    #[async_trait]
+    impl Middleware<(), ()> for TagMiddleware {
+        async fn call(&self, ctx: &mut RequestContext<(), ()>) -> HttpResponse {
+            let mut resp = self.next.call(ctx).await;
+            resp.headers_mut()
+                .append("x-tag", hyper::header::HeaderValue::from_static(self.tag));
+            resp
+        }
+    }
+
+    async fn call_router<E>(
+        router: &Router<(), (), E>,
+        method: Method,
+        path: &'static str,
+    ) -> HttpResponse
+    where
+        E: Send + Sync + 'static,
+    {
+        let mut request = HttpRequest::new(HttpBody::empty());
+        *request.method_mut() = method;
+        *request.uri_mut() = hyper::Uri::from_static(path);
+        call(router, request).await
+    }
+
+    fn tags(resp: &HttpResponse) -> Vec<&str> {
+        resp.headers()
+            .get_all("x-tag")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn router_nests_with_layers() {
+        let mut users = Router::new().layer(Tag("users"));
+        users.add_wrapped(
+            vec![Method::GET],
+            "/:id{u16}",
+            handlers::closure(|_, params: RouteParameters| async move {
+                let id = params.get::<u16>("id").unwrap();
+                Ok(respond::status(Status::from_u16(200 + id).unwrap()))
+            }),
+        );
+
+        let mut admin = Router::new().layer(Tag("inner")).layer(Tag("outer"));
+        admin.add(vec![Method::GET], "/", AbcHandler {});
+        admin.nest("/users/", users);
+
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/", IndexHandler {});
+        router.nest("/admin", admin);
+        router.apply_layers();
+
+        let resp = call_router(&router, Method::GET, "/").await;
+        assert_eq!(Status::OK, resp.status());
+        assert!(tags(&resp).is_empty());
+
+        let resp = call_router(&router, Method::GET, "/admin").await;
+        assert_eq!(Status::CREATED, resp.status());
+        assert_eq!(vec!["inner", "outer"], tags(&resp));
+
+        let resp = call_router(&router, Method::GET, "/admin/users/4").await;
+        assert_eq!(Status::NO_CONTENT, resp.status());
+        assert_eq!(vec!["users", "inner", "outer"], tags(&resp));
+
+        // Nested routes still conflict with existing ones, and none are
+        // grafted if any of them do
+        let mut other = Router::new();
+        other.add(vec![Method::GET], "/new", AbcHandler {});
+        other.add(vec![Method::GET], "/users/:id{u16}", AbcHandler {});
+        assert!(matches!(
+            router.try_nest("/admin", other),
+            Err(RouteError::DuplicateRoute { .. })
+        ));
+        let resp = call_router(&router, Method::GET, "/admin/new").await;
+        assert_eq!(Status::NOT_FOUND, resp.status());
+    }
+
+    #[tokio::test]
+    async fn router_nests_error_handlers() {
+        let failing =
+            || handlers::closure(|_, _| async move { Err::<Response, _>(String::from("failed")) });
+
+        // Kept without any layers
+        let mut api: Router<(), (), String> =
+            Router::new().on_error(|_, e| Response::Text(Status::IM_A_TEAPOT, e));
+        api.add_wrapped(vec![Method::GET], "/fail", failing());
+
+        let mut router: Router<(), (), String> = Router::new();
+        router.add_wrapped(vec![Method::GET], "/fail", failing());
+        router.nest("/api", api);
+
+        let resp = call_router(&router, Method::GET, "/fail").await;
+        assert_eq!(Status::INTERNAL_SERVER_ERROR, resp.status());
+        let resp = call_router(&router, Method::GET, "/api/fail").await;
+        assert_eq!(Status::IM_A_TEAPOT, resp.status());
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("failed", body);
+    }
+
+    /// Labels responses with the template of the route matched before the
+    /// request reached it
+    #[derive(Clone)]
+    struct RouteLabel;
+
+    struct RouteLabelMiddleware {
+        next: Arc<dyn Middleware<(), ()>>,
+    }
+
+    impl ToMiddleware<(), ()> for RouteLabel {
+        fn wrap(self, next: Arc<dyn Middleware<(), ()>>) -> Arc<dyn Middleware<(), ()>> {
+            Arc::new(RouteLabelMiddleware { next })
+        }
+    }
+
+    #[async_trait]
+    impl Middleware<(), ()> for RouteLabelMiddleware {
+        async fn call(&self, ctx: &mut RequestContext<(), ()>) -> HttpResponse {
+            let label = match ctx.route.as_ref() {
+                Some(route) => route.template.clone(),
+                None => String::from("none"),
+            };
+            let mut resp = self.next.call(ctx).await;
+            resp.headers_mut().insert(
+                "x-route",
+                hyper::header::HeaderValue::from_str(&label).unwrap(),
+            );
+            resp
+        }
+    }
+
+    #[tokio::test]
+    async fn router_exposes_route_match() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/abc/:id{i32}", AbcIdHandler {});
+        let mut users = Router::new().layer(RouteLabel);
+        users.add(vec![Method::GET], "/:name", AbcStrHandler {});
+        router.nest("/users", users);
+        router.apply_layers();
+
+        // Resolved ahead of the middleware stack, as by the server
+        let router = Arc::new(router);
+        let stack = RouteLabel.wrap(router.clone());
+        for (path, template) in [
+            ("/abc/5", "/abc/:id{i32}"),
+            ("/abc/def", "none"),
+            ("/users/plaid", "/users/:name"),
+        ] {
+            let mut request = HttpRequest::new(HttpBody::empty());
+            *request.uri_mut() = path.parse().unwrap();
+            let mut ctx = context(request);
+            ctx.route = router.resolve(&ctx.request);
+            let resp = stack.call(&mut ctx).await;
+            assert_eq!(
+                vec![template],
+                resp.headers()
+                    .get_all("x-route")
+                    .iter()
+                    .map(|v| v.to_str().unwrap())
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        // The router replaces the match with its own
+        let mut request = HttpRequest::new(HttpBody::empty());
+        *request.uri_mut() = hyper::Uri::from_static("/abc/5");
+        let mut ctx = context(request);
+        router.call(&mut ctx).await;
+        let route = ctx.route.expect("route should be matched");
+        assert_eq!("/abc/:id{i32}", route.template);
+        assert_eq!(5, route.params.get::<i32>("id").unwrap());
+    }
+
+    #[test]
+    fn router_lists_routes() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/", IndexHandler {});
+        router.add(vec![Method::POST, Method::GET], "/abc", AbcHandler {});
+        router.add(vec![Method::GET], "/abc/def", AbcDefHandler {});
+        router.add(
+            vec![Method::DELETE, Method::GET],
+            "/abc/:id{i32}",
+            AbcIdHandler {},
+        );
+        router.add(vec![Method::GET], "/abc/:name", AbcStrHandler {});
+        router.add(vec![Method::GET], "/static/*path", AbcGhiHandler {});
+
+        let routes = router
+            .routes()
+            .map(|(template, methods)| (String::from(template), methods))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (String::from("/"), vec![Method::GET]),
+                (String::from("/abc"), vec![Method::GET, Method::POST]),
+                (
+                    String::from("/abc/:id{i32}"),
+                    vec![Method::GET, Method::DELETE]
+                ),
+                (String::from("/abc/:name"), vec![Method::GET]),
+                (String::from("/abc/def"), vec![Method::GET]),
+                (String::from("/static/*path"), vec![Method::GET]),
+            ],
+            routes
+        );
+
+        assert_eq!(
+            "/ [GET]\n  abc [GET, POST]\n    def [GET]\n    :id{i32} [GET, DELETE]\n    :name [GET]\n  static\n    *path [GET]\n",
+            format!("{:?}", router)
+        );
+
+        assert_eq!(
+            "METHODS      ROUTE\n\
+             GET          /\n\
+             GET, POST    /abc\n\
+             GET, DELETE  /abc/:id{i32}\n\
+             GET          /abc/:name\n\
+             GET          /abc/def\n\
+             GET          /static/*path\n",
+            router.route_table()
+        );
+    }
+
     #[tokio::test]
     async fn router_handles_options() {
         let mut router = Router::new().handle_options(true);
@@ -324,12 +1291,647 @@ mod tests {
         router.add(vec![Method::PUT], "/abc", AbcHandler {});
 
         if let RouterResult::Options(opts) = router.route("/abc", &Method::OPTIONS) {
-            assert_eq!(3, opts.len());
-            for method in &[Method::GET, Method::PUT, Method::OPTIONS] {
-                assert!(opts.contains(&method))
+            assert_eq!(4, opts.len());
+            for method in &[Method::GET, Method::HEAD, Method::PUT, Method::OPTIONS] {
+                assert!(opts.contains(method))
             }
         } else {
             panic!("Didn't get options result")
         }
     }
+
+    #[tokio::test]
+    async fn router_handles_head() {
+        let mut router = Router::new();
+        router.add_wrapped(
+            vec![Method::GET],
+            "/text",
+            handlers::closure(|_, _| async {
+                Ok(Response::Text(Status::OK, String::from("plaid")))
+            }),
+        );
+        router.add_wrapped(
+            vec![Method::HEAD],
+            "/explicit",
+            handlers::closure(|_, _| async { Ok(respond::status(Status::ACCEPTED)) }),
+        );
+        router.add(vec![Method::PUT], "/put", AbcHandler {});
+
+        let resp = call_router(&router, Method::HEAD, "/text").await;
+        assert_eq!(Status::OK, resp.status());
+        assert_eq!("5", resp.headers()[hyper::header::CONTENT_LENGTH]);
+        assert_eq!("text/plain", resp.headers()[hyper::header::CONTENT_TYPE]);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let resp = call_router(&router, Method::HEAD, "/explicit").await;
+        assert_eq!(Status::ACCEPTED, resp.status());
+        let resp = call_router(&router, Method::HEAD, "/put").await;
+        assert_eq!(Status::METHOD_NOT_ALLOWED, resp.status());
+
+        let router = router.handle_head(false);
+        let resp = call_router(&router, Method::HEAD, "/text").await;
+        assert_eq!(Status::METHOD_NOT_ALLOWED, resp.status());
+    }
+
+    #[tokio::test]
+    async fn router_responds_not_allowed() {
+        let mut router = Router::new();
+        router.add(vec![Method::PUT, Method::GET], "/abc", AbcHandler {});
+
+        let resp = call_router(&router, Method::DELETE, "/abc").await;
+        assert_eq!(Status::METHOD_NOT_ALLOWED, resp.status());
+        assert_eq!(
+            "GET, HEAD, PUT, OPTIONS",
+            resp.headers()[hyper::header::ALLOW]
+        );
+
+        let resp = call_router(&router, Method::OPTIONS, "/abc").await;
+        assert_eq!(
+            "GET, HEAD, PUT, OPTIONS",
+            resp.headers()[hyper::header::ALLOW]
+        );
+
+        let mut router = router
+            .handle_options(false)
+            .handle_head(false)
+            .on_not_found(|_| Response::Text(Status::NOT_FOUND, String::from("nothing here")))
+            .on_method_not_allowed(|_, allowed| {
+                Response::Text(
+                    Status::METHOD_NOT_ALLOWED,
+                    format!("try one of {} methods", allowed.len()),
+                )
+            });
+        router.add(vec![Method::GET], "/", IndexHandler {});
+
+        let resp = call_router(&router, Method::DELETE, "/abc").await;
+        assert_eq!(Status::METHOD_NOT_ALLOWED, resp.status());
+        assert_eq!("GET, PUT", resp.headers()[hyper::header::ALLOW]);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("try one of 2 methods", body);
+
+        let resp = call_router(&router, Method::GET, "/def").await;
+        assert_eq!(Status::NOT_FOUND, resp.status());
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("nothing here", body);
+    }
+
+    #[tokio::test]
+    async fn router_handles_trailing_slashes() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/abc", AbcHandler {});
+        router.add(vec![Method::GET], "/abc/def/", AbcDefHandler {});
+        router.add_with_options(
+            vec![Method::GET],
+            "/abc/ghi",
+            RouteOptions::new().trailing_slash(TrailingSlash::MatchBoth),
+            AbcGhiHandler {},
+        );
+        let mut admin = Router::new();
+        admin.add(vec![Method::GET], "/", IndexHandler {});
+        router.nest("/admin", admin);
+
+        // Strict by default
+        for (path, status) in [
+            ("/abc", Status::CREATED),
+            ("/abc/", Status::NOT_FOUND),
+            ("/abc/def/", Status::ACCEPTED),
+            ("/abc/def", Status::NOT_FOUND),
+            ("/abc/ghi", Status::NON_AUTHORITATIVE_INFORMATION),
+            ("/abc/ghi/", Status::NON_AUTHORITATIVE_INFORMATION),
+            ("/admin", Status::OK),
+            ("/admin/", Status::NOT_FOUND),
+        ] {
+            assert_eq!(
+                status,
+                call_router(&router, Method::GET, path).await.status()
+            );
+        }
+
+        let router = router.trailing_slash(TrailingSlash::Redirect(Status::MOVED_PERMANENTLY));
+        for (path, location) in [
+            ("/abc/?page=2", "/abc?page=2"),
+            ("/abc//", "/abc"),
+            ("/abc/def", "/abc/def/"),
+            ("/admin/", "/admin"),
+        ] {
+            let resp = call_router(&router, Method::GET, path).await;
+            assert_eq!(Status::MOVED_PERMANENTLY, resp.status());
+            assert_eq!(location, resp.headers()[hyper::header::LOCATION]);
+        }
+        let resp = call_router(&router, Method::GET, "/abc/ghi/").await;
+        assert_eq!(Status::NON_AUTHORITATIVE_INFORMATION, resp.status());
+
+        let router = router.trailing_slash(TrailingSlash::MatchBoth);
+        let resp = call_router(&router, Method::GET, "/abc/def").await;
+        assert_eq!(Status::ACCEPTED, resp.status());
+    }
+
+    #[tokio::test]
+    async fn router_normalizes_paths() {
+        let mut router = Router::new();
+        router.add(vec![Method::GET], "/abc", AbcHandler {});
+        router.add(vec![Method::GET], "/abc/def", AbcDefHandler {});
+        router.add_wrapped(
+            vec![Method::GET],
+            "/users/:name",
+            handlers::closure(|_, params: RouteParameters| async move {
+                let name = params.get::<String>("name").unwrap();
+                Ok(Response::Text(Status::OK, name))
+            }),
+        );
+
+        let resp = call_router(&router, Method::GET, "/users/John%20Doe").await;
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("John Doe", body);
+
+        for (path, status) in [
+            ("//abc", Status::CREATED),
+            ("/abc/./def", Status::ACCEPTED),
+            ("/abc/ghi/../def", Status::ACCEPTED),
+            ("/ABC", Status::NOT_FOUND),
+            ("/abc/%zz", Status::BAD_REQUEST),
+        ] {
+            assert_eq!(
+                status,
+                call_router(&router, Method::GET, path).await.status()
+            );
+        }
+
+        let router = router
+            .case_insensitive(true)
+            .collapse_slashes(false)
+            .dot_segments(DotSegments::Reject)
+            .encoded_slashes(EncodedSlashes::Reject);
+        for (path, status) in [
+            ("/ABC/Def", Status::ACCEPTED),
+            ("/abc//def", Status::NOT_FOUND),
+            ("/abc/./def", Status::BAD_REQUEST),
+            ("/users/a%2Fb", Status::BAD_REQUEST),
+        ] {
+            assert_eq!(
+                status,
+                call_router(&router, Method::GET, path).await.status()
+            );
+        }
+
+        // Redirects go to the normalized path
+        let router = router
+            .dot_segments(DotSegments::Resolve)
+            .redirect_trailing_slash(true);
+        let resp = call_router(&router, Method::GET, "/abc/def/.").await;
+        assert_eq!(Status::PERMANENT_REDIRECT, resp.status());
+        assert_eq!("/abc/def", resp.headers()[hyper::header::LOCATION]);
+    }
+
+    #[tokio::test]
+    async fn router_applies_guards() {
+        struct TenantHandler;
+        #[async_trait]
+        impl Handler<(), (), BasicError> for TenantHandler {
+            async fn handle(
+                &self,
+                _: &mut RequestContext<(), ()>,
+                params: RouteParameters,
+            ) -> Result<Response, BasicError> {
+                let tenant = params.get::<String>("tenant").unwrap();
+                Ok(Response::Text(Status::OK, tenant))
+            }
+        }
+
+        let mut router = Router::new();
+        router.add_with_options(
+            vec![Method::GET],
+            "/users",
+            RouteOptions::new().host(":tenant.example.com"),
+            TenantHandler {},
+        );
+        router.add(vec![Method::GET], "/users", AbcHandler {});
+        router.add_with_options(
+            vec![Method::GET],
+            "/api/items",
+            RouteOptions::new().header("Accept-Version", "2"),
+            AbcDefHandler {},
+        );
+        router.add(vec![Method::GET], "/api/:name", AbcStrHandler {});
+        router.add_with_options(
+            vec![Method::POST],
+            "/upload",
+            RouteOptions::new().content_type("application/json"),
+            AbcGhiHandler {},
+        );
+        router.add_with_options(
+            vec![Method::POST],
+            "/upload",
+            RouteOptions::new().guard(|request| request.uri().query() == Some("debug")),
+            AbcIdHandler {},
+        );
+
+        let request = |method: Method, uri: &str, headers: &[(&'static str, &'static str)]| {
+            let mut request = hyper::Request::builder().method(method).uri(uri);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            request.body(HttpBody::empty()).unwrap()
+        };
+
+        let host = [("host", "Acme.Example.com:8080")];
+        let resp = call(&router, request(Method::GET, "/users", &host)).await;
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("acme", body);
+
+        for (method, uri, headers, status) in [
+            (
+                Method::GET,
+                "/users",
+                vec![("host", "example.com")],
+                Status::CREATED,
+            ),
+            (
+                Method::GET,
+                "http://a.example.org/users",
+                vec![],
+                Status::CREATED,
+            ),
+            (
+                Method::GET,
+                "/api/items",
+                vec![("accept-version", "2")],
+                Status::ACCEPTED,
+            ),
+            (Method::GET, "/api/items", vec![], Status::RESET_CONTENT),
+            (
+                Method::POST,
+                "/upload",
+                vec![("content-type", "application/json; charset=utf-8")],
+                Status::NON_AUTHORITATIVE_INFORMATION,
+            ),
+            (
+                Method::POST,
+                "/upload?debug",
+                vec![("content-type", "text/plain")],
+                Status::NO_CONTENT,
+            ),
+            (
+                Method::POST,
+                "/upload",
+                vec![("content-type", "text/plain")],
+                Status::NOT_FOUND,
+            ),
+        ] {
+            let resp = call(&router, request(method, uri, &headers)).await;
+            assert_eq!(status, resp.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn router_negotiates_responses() {
+        #[derive(serde::Serialize)]
+        struct User {
+            name: &'static str,
+            id: i32,
+        }
+
+        let handler = || {
+            handlers::closure(|_, _| async move {
+                let user = User {
+                    name: "John",
+                    id: 1,
+                };
+                Ok(respond::negotiated(Status::OK, &user).unwrap())
+            })
+        };
+        let mut router: Router<(), (), BasicError> = Router::new();
+        router.add_wrapped(vec![Method::GET], "/user", handler());
+        // Routes nested from a layered router are negotiated too
+        let mut api = Router::new().layer(Tag("api"));
+        api.add_wrapped(vec![Method::GET], "/user", handler());
+        router.nest("/api", api);
+
+        for (accept, status, content_type, body) in [
+            (
+                None,
+                Status::OK,
+                "application/json",
+                r#"{"id":1,"name":"John"}"#,
+            ),
+            (
+                Some("text/html, application/x-www-form-urlencoded;q=0.9, */*;q=0.1"),
+                Status::OK,
+                "application/x-www-form-urlencoded",
+                "name=John&id=1",
+            ),
+            (
+                Some("text/*"),
+                Status::OK,
+                "text/plain",
+                "{\n  \"id\": 1,\n  \"name\": \"John\"\n}",
+            ),
+            (Some("image/png"), Status::NOT_ACCEPTABLE, "", ""),
+        ] {
+            for uri in ["/user", "/api/user"] {
+                let mut request = hyper::Request::builder().uri(uri);
+                if let Some(accept) = accept {
+                    request = request.header(hyper::header::ACCEPT, accept);
+                }
+                let resp = call(&router, request.body(HttpBody::empty()).unwrap()).await;
+                assert_eq!(status, resp.status());
+                let resp_type = resp
+                    .headers()
+                    .get(hyper::header::CONTENT_TYPE)
+                    .map(|t| t.to_str().unwrap())
+                    .unwrap_or_default();
+                assert_eq!(content_type, resp_type);
+                let resp_body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+                assert_eq!(body, resp_body);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn router_answers_conditional_requests() {
+        let mut router: Router<(), (), BasicError> = Router::new().auto_etag(true);
+        router.add_wrapped(
+            vec![Method::GET],
+            "/abc",
+            handlers::closure(
+                |_, _| async move { Ok(Response::Text(Status::OK, String::from("abc"))) },
+            ),
+        );
+        router.add_wrapped(
+            vec![Method::GET, Method::PUT],
+            "/tagged",
+            handlers::closure(|_, _| async move {
+                Ok(Response::Text(Status::OK, String::from("tagged")).with_etag(&ETag::weak("v1")))
+            }),
+        );
+
+        let request = |path: &'static str, headers: Vec<(&'static str, String)>| {
+            let mut request = hyper::Request::builder().uri(path);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.body(HttpBody::empty()).unwrap()
+        };
+
+        let resp = call(&router, request("/abc", vec![])).await;
+        let etag = ETag::from_bytes(b"abc").to_string();
+        assert_eq!(etag, resp.headers()[hyper::header::ETAG]);
+
+        let resp = call(
+            &router,
+            request("/abc", vec![("if-none-match", etag.clone())]),
+        )
+        .await;
+        assert_eq!(Status::NOT_MODIFIED, resp.status());
+        assert_eq!(etag, resp.headers()[hyper::header::ETAG]);
+        assert!(!resp.headers().contains_key(hyper::header::CONTENT_TYPE));
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let resp = call(
+            &router,
+            request("/tagged", vec![("if-none-match", String::from("\"v1\""))]),
+        )
+        .await;
+        assert_eq!(Status::NOT_MODIFIED, resp.status());
+        let resp = call(
+            &router,
+            request("/tagged", vec![("if-match", String::from("\"v1\""))]),
+        )
+        .await;
+        assert_eq!(Status::PRECONDITION_FAILED, resp.status());
+
+        // A PUT has already made its changes by the time the router sees its
+        // response, so its preconditions are left to the handler
+        for (name, value) in [("if-match", "\"v2\""), ("if-none-match", "*")] {
+            let mut request = request("/tagged", vec![(name, String::from(value))]);
+            *request.method_mut() = Method::PUT;
+            assert_eq!(Status::OK, call(&router, request).await.status());
+        }
+
+        let router = router.conditional_requests(false);
+        let mut request = HttpRequest::new(HttpBody::empty());
+        *request.uri_mut() = hyper::Uri::from_static("/tagged");
+        request.headers_mut().insert(
+            hyper::header::IF_NONE_MATCH,
+            hyper::header::HeaderValue::from_static("\"v1\""),
+        );
+        assert_eq!(Status::OK, call(&router, request).await.status());
+    }
+
+    #[tokio::test]
+    async fn router_answers_range_requests() {
+        let mut router: Router<(), (), BasicError> = Router::new();
+        router.add_wrapped(
+            vec![Method::GET],
+            "/bytes",
+            handlers::closure(|_, _| async move {
+                Ok(Response::Bytes {
+                    status: Status::OK,
+                    body: b"0123456789".to_vec(),
+                    md5: None,
+                })
+            }),
+        );
+
+        let request = |range: &'static str| {
+            hyper::Request::builder()
+                .uri("/bytes")
+                .header(hyper::header::RANGE, range)
+                .body(HttpBody::empty())
+                .unwrap()
+        };
+
+        let resp = call(&router, request("bytes=-3")).await;
+        assert_eq!(Status::PARTIAL_CONTENT, resp.status());
+        assert_eq!("bytes", resp.headers()[hyper::header::ACCEPT_RANGES]);
+        assert_eq!("bytes 7-9/10", resp.headers()[hyper::header::CONTENT_RANGE]);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!("789", body);
+
+        let resp = call(&router, request("bytes=0-0,5-5")).await;
+        assert_eq!(Status::PARTIAL_CONTENT, resp.status());
+        let content_type = resp.headers()[hyper::header::CONTENT_TYPE]
+            .to_str()
+            .unwrap();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+
+        let resp = call(&router, request("bytes=10-")).await;
+        assert_eq!(Status::RANGE_NOT_SATISFIABLE, resp.status());
+        assert_eq!("bytes */10", resp.headers()[hyper::header::CONTENT_RANGE]);
+
+        let whole = || {
+            hyper::Request::builder()
+                .uri("/bytes")
+                .body(HttpBody::empty())
+                .unwrap()
+        };
+        let resp = call(&router, whole()).await;
+        assert_eq!("bytes", resp.headers()[hyper::header::ACCEPT_RANGES]);
+
+        // Ranges are only advertised when they're served
+        let router = router.range_requests(false);
+        let resp = call(&router, request("bytes=0-1")).await;
+        assert_eq!(Status::OK, resp.status());
+        assert!(!resp.headers().contains_key(hyper::header::ACCEPT_RANGES));
+        let resp = call(&router, whole()).await;
+        assert!(!resp.headers().contains_key(hyper::header::ACCEPT_RANGES));
+    }
+
+    struct Echo;
+    #[async_trait]
+    impl Handler<(), (), BasicError> for Echo {
+        async fn handle(
+            &self,
+            ctx: &mut RequestContext<(), ()>,
+            _: RouteParameters,
+        ) -> Result<Response, BasicError> {
+            match ctx.body().await {
+                Ok(body) => Ok(Response::Bytes {
+                    status: Status::OK,
+                    body: body.to_vec(),
+                    md5: None,
+                }),
+                Err(_) => Ok(respond::status(Status::BAD_REQUEST)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn router_limits_request_bodies() {
+        let mut router: Router<(), (), BasicError> = Router::new().body_limit(8);
+        router.add(vec![Method::POST], "/echo", Echo);
+        router.add_with_options(
+            vec![Method::POST],
+            "/large",
+            RouteOptions::new().body_limit(16),
+            Echo,
+        );
+
+        let request = |path: &'static str, body: HttpBody, length: Option<usize>| {
+            let mut request = hyper::Request::builder().method(Method::POST).uri(path);
+            if let Some(length) = length {
+                request = request.header(hyper::header::CONTENT_LENGTH, length);
+            }
+            request.body(body).unwrap()
+        };
+        let chunked = |chunks: &[&'static str]| {
+            let chunks = chunks
+                .iter()
+                .map(|chunk| Ok::<_, std::io::Error>(*chunk))
+                .collect::<Vec<_>>();
+            HttpBody::wrap_stream(futures_util::stream::iter(chunks))
+        };
+
+        let resp = call(
+            &router,
+            request("/echo", HttpBody::from("12345678"), Some(8)),
+        )
+        .await;
+        assert_eq!(Status::OK, resp.status());
+
+        // Rejected by Content-Length, before reading the body
+        let resp = call(
+            &router,
+            request("/echo", HttpBody::from("123456789"), Some(9)),
+        )
+        .await;
+        assert_eq!(Status::PAYLOAD_TOO_LARGE, resp.status());
+
+        // Rejected while streaming
+        let resp = call(&router, request("/echo", chunked(&["12345", "6789"]), None)).await;
+        assert_eq!(Status::PAYLOAD_TOO_LARGE, resp.status());
+
+        let resp = call(
+            &router,
+            request("/large", chunked(&["12345", "6789"]), None),
+        )
+        .await;
+        assert_eq!(Status::OK, resp.status());
+        let resp = call(
+            &router,
+            request("/large", HttpBody::from("x".repeat(17)), Some(17)),
+        )
+        .await;
+        assert_eq!(Status::PAYLOAD_TOO_LARGE, resp.status());
+    }
+
+    #[tokio::test]
+    async fn router_keeps_handlers_payload_too_large_responses() {
+        use crate::context::JsonError;
+
+        struct Parse;
+        #[async_trait]
+        impl Handler<(), (), JsonError> for Parse {
+            async fn handle(
+                &self,
+                ctx: &mut RequestContext<(), ()>,
+                _: RouteParameters,
+            ) -> Result<Response, JsonError> {
+                ctx.body_json::<serde_json::Value>().await?;
+                Ok(respond::status(Status::NO_CONTENT))
+            }
+        }
+
+        let mut router = Router::new().body_limit(8).problem_errors();
+        router.add(vec![Method::POST], "/parse", Parse);
+
+        let chunks = ["[1, 2, ", "3, 4]"].map(Ok::<_, std::io::Error>);
+        let request = hyper::Request::builder()
+            .method(Method::POST)
+            .uri("/parse")
+            .body(HttpBody::wrap_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let resp = call(&router, request).await;
+        assert_eq!(Status::PAYLOAD_TOO_LARGE, resp.status());
+        assert_eq!(
+            "application/problem+json",
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(413, problem["status"]);
+    }
+
+    #[tokio::test]
+    async fn router_answers_problem_errors() {
+        enum ApiError {
+            NotFound(i32),
+        }
+
+        impl IntoProblem for ApiError {
+            fn into_problem(self) -> Problem {
+                match self {
+                    ApiError::NotFound(id) => Problem::new(Status::NOT_FOUND)
+                        .detail(&format!("No user with id {}", id))
+                        .extension("id", id),
+                }
+            }
+        }
+
+        let mut router: Router<(), (), ApiError> = Router::new().problem_errors();
+        router.add_wrapped(
+            vec![Method::GET],
+            "/users/:id{i32}",
+            handlers::closure(|_, params: RouteParameters| async move {
+                Err::<Response, _>(ApiError::NotFound(params.first_i32().unwrap_or_default()))
+            }),
+        );
+
+        let request = hyper::Request::builder()
+            .uri("/users/7")
+            .body(HttpBody::empty())
+            .unwrap();
+        let resp = call(&router, request).await;
+        assert_eq!(Status::NOT_FOUND, resp.status());
+        assert_eq!(
+            "application/problem+json",
+            resp.headers()[hyper::header::CONTENT_TYPE]
+        );
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("No user with id 7", problem["detail"]);
+        assert_eq!(7, problem["id"]);
+    }
 }
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use crate::prelude::*;
+
+type Predicate = Arc<dyn Fn(&HttpRequest) -> bool + Send + Sync>;
+
+/// Named parameters captured by guards, as `(name, value)` pairs
+pub(crate) type Captures = Vec<(String, String)>;
+
+/// A condition on a request (other than its path and method) that must hold
+/// for a route to match. Added to routes with [`RouteOptions`].
+///
+/// [`RouteOptions`]: super::RouteOptions
+#[derive(Clone)]
+pub(crate) enum Guard {
+    Host(HostPattern),
+    Header(hyper::header::HeaderName, hyper::header::HeaderValue),
+    ContentType(String),
+    Custom(Predicate),
+}
+
+impl Guard {
+    pub(crate) fn custom(predicate: impl Fn(&HttpRequest) -> bool + Send + Sync + 'static) -> Self {
+        Guard::Custom(Arc::new(predicate))
+    }
+
+    /// Check the guard against a request, returning any parameters it
+    /// captured if it passes
+    pub(crate) fn check(&self, request: &HttpRequest) -> Option<Captures> {
+        match self {
+            Guard::Host(pattern) => pattern.capture(&host(request)?),
+            Guard::Header(name, value) => {
+                let passes = request.headers().get_all(name).iter().any(|v| v == value);
+                passes.then(Vec::new)
+            }
+            Guard::ContentType(expected) => {
+                let content_type = request
+                    .headers()
+                    .get(hyper::header::CONTENT_TYPE)?
+                    .to_str()
+                    .ok()?;
+                media_type_matches(expected, content_type).then(Vec::new)
+            }
+            Guard::Custom(predicate) => predicate(request).then(Vec::new),
+        }
+    }
+
+    /// Whether the guard passes the same requests as another. Custom guards
+    /// are only the same as their own clones.
+    fn same_as(&self, other: &Guard) -> bool {
+        match (self, other) {
+            (Guard::Host(a), Guard::Host(b)) => a.labels == b.labels,
+            (Guard::Header(name_a, value_a), Guard::Header(name_b, value_b)) => {
+                name_a == name_b && value_a == value_b
+            }
+            (Guard::ContentType(a), Guard::ContentType(b)) => a.eq_ignore_ascii_case(b),
+            (Guard::Custom(a), Guard::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Guard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Guard::Host(pattern) => write!(f, "Host({})", pattern.pattern),
+            Guard::Header(name, value) => write!(f, "Header({}: {:?})", name, value),
+            Guard::ContentType(content_type) => write!(f, "ContentType({})", content_type),
+            Guard::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Check every guard against a request, returning the parameters they
+/// captured if they all pass
+pub(crate) fn check_all(guards: &[Guard], request: &HttpRequest) -> Option<Captures> {
+    let mut captures = Vec::new();
+    for guard in guards {
+        captures.extend(guard.check(request)?);
+    }
+    Some(captures)
+}
+
+/// The names of the parameters the guards capture, in order
+pub(crate) fn capture_names(guards: &[Guard]) -> impl Iterator<Item = &str> {
+    guards.iter().flat_map(|guard| match guard {
+        Guard::Host(pattern) => pattern.parameter_names(),
+        _ => Vec::new(),
+    })
+}
+
+/// Whether two routes' guards pass the same requests, in any order
+pub(crate) fn same_guards(a: &[Guard], b: &[Guard]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|guard| b.iter().any(|other| guard.same_as(other)))
+}
+
+/// A host name pattern like `:tenant.example.com`, where `:name` labels match
+/// any single label and capture it as a parameter
+#[derive(Clone)]
+pub(crate) struct HostPattern {
+    pattern: String,
+    labels: Vec<HostLabel>,
+}
+
+#[derive(Clone, PartialEq)]
+enum HostLabel {
+    Static(String),
+    Parameter(String),
+}
+
+impl HostPattern {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let labels = pattern
+            .trim_end_matches('.')
+            .split('.')
+            .map(|label| match label.strip_prefix(':') {
+                Some(name) => HostLabel::Parameter(String::from(name)),
+                None => HostLabel::Static(label.to_ascii_lowercase()),
+            })
+            .collect();
+        Self {
+            pattern: String::from(pattern),
+            labels,
+        }
+    }
+
+    fn parameter_names(&self) -> Vec<&str> {
+        self.labels
+            .iter()
+            .filter_map(|label| match label {
+                HostLabel::Parameter(name) => Some(name.as_str()),
+                HostLabel::Static(_) => None,
+            })
+            .collect()
+    }
+
+    fn capture(&self, host: &str) -> Option<Captures> {
+        let host = host.trim_end_matches('.');
+        let labels = host.split('.').collect::<Vec<&str>>();
+        if labels.len() != self.labels.len() {
+            return None;
+        }
+
+        let mut captures = Vec::new();
+        for (pattern, label) in self.labels.iter().zip(labels) {
+            match pattern {
+                HostLabel::Static(expected) if expected.eq_ignore_ascii_case(label) => {}
+                HostLabel::Static(_) => return None,
+                HostLabel::Parameter(_) if label.is_empty() => return None,
+                HostLabel::Parameter(name) => {
+                    captures.push((name.clone(), label.to_ascii_lowercase()))
+                }
+            }
+        }
+        Some(captures)
+    }
+}
+
+/// The host a request was sent to (from the `Host` header, or the URI for
+/// HTTP/2), without the port
+fn host(request: &HttpRequest) -> Option<String> {
+    let host = match request.headers().get(hyper::header::HOST) {
+        Some(host) => host.to_str().ok()?,
+        None => request.uri().host()?,
+    };
+    // Don't split IPv6 addresses on their colons
+    let host = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    Some(String::from(host))
+}
+
+/// Whether a `Content-Type` matches an expected media type, ignoring
+/// parameters (like `charset`) and case. The expected type can use a wildcard
+/// subtype, like `text/*`.
+pub(crate) fn media_type_matches(expected: &str, content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match expected.strip_suffix("/*") {
+        Some(expected_type) => essence
+            .split('/')
+            .next()
+            .is_some_and(|t| t.eq_ignore_ascii_case(expected_type)),
+        None => essence.eq_ignore_ascii_case(expected),
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use super::ErrorHandler;
+use crate::prelude::*;
+
+/// Builds a new instance of some middleware around the next item in a stack.
+/// Each handler in a layered router gets its own stack.
+pub(crate) type Layer<G, L> =
+    Arc<dyn Fn(Arc<dyn Middleware<G, L>>) -> Arc<dyn Middleware<G, L>> + Send + Sync>;
+
+pub(crate) fn layer<G, L, M>(middleware: M) -> Layer<G, L>
+where
+    M: ToMiddleware<G, L> + Clone + Send + Sync + 'static,
+{
+    Arc::new(move |next| middleware.clone().wrap(next))
+}
+
+/// Wrap a handler in a middleware stack built from `layers`. As with the
+/// server, the last layer is the outermost. Errors from the handler are
+/// converted with `error_handler` before they pass back through the stack.
+pub(crate) fn layered<G, L, E>(
+    handler: WrappedHandler<G, L, E>,
+    layers: &[Layer<G, L>],
+    error_handler: ErrorHandler<G, L, E>,
+) -> WrappedHandler<G, L, E>
+where
+    G: Send + Sync + 'static,
+    L: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    let endpoint: Arc<dyn Middleware<G, L>> = Arc::new(Endpoint {
+        handler,
+        error_handler,
+    });
+    let stack = layers.iter().fold(endpoint, |next, layer| layer(next));
+    Arc::new(Layered { stack })
+}
+
+/// Route parameters on their way through a layered middleware stack to the
+/// handler
+struct LayeredParameters(RouteParameters);
+
+struct Layered<G, L>
+where
+    G: 'static,
+    L: 'static,
+{
+    stack: Arc<dyn Middleware<G, L>>,
+}
+
+#[async_trait]
+impl<G, L, E> Handler<G, L, E> for Layered<G, L>
+where
+    G: Send + Sync + 'static,
+    L: Send + Sync + 'static,
+{
+    async fn handle(
+        &self,
+        ctx: &mut RequestContext<G, L>,
+        params: RouteParameters,
+    ) -> Result<Response, E> {
+        ctx.request
+            .extensions_mut()
+            .insert(LayeredParameters(params));
+        Ok(Response::Custom(self.stack.call(ctx).await))
+    }
+}
+
+struct Endpoint<G, L, E>
+where
+    G: 'static,
+    L: 'static,
+{
+    handler: WrappedHandler<G, L, E>,
+    error_handler: ErrorHandler<G, L, E>,
+}
+
+#[async_trait]
+impl<G, L, E> Middleware<G, L> for Endpoint<G, L, E>
+where
+    G: Send + Sync + 'static,
+    L: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    async fn call(&self, ctx: &mut RequestContext<G, L>) -> HttpResponse {
+        let params = ctx
+            .request
+            .extensions_mut()
+            .remove::<LayeredParameters>()
+            .map(|LayeredParameters(params)| params)
+            .unwrap_or_else(RouteParameters::new);
+
+        let response = match self.handler.handle(ctx, params).await {
+            Ok(response) => response,
+            Err(e) => (self.error_handler)(ctx, e),
+        };
+        // The router only negotiates the `Response` it gets back, which has
+        // already been converted by the time it leaves the stack
+        HttpResponse::from(response.negotiate(&ctx.request))
+    }
+}
@@ -103,10 +103,9 @@ macro_rules! __internal_routes {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
     use crate::prelude::*;
     use crate::routes::RouterResult;
+    use crate::testing::context;
 
     enum BasicError {}
 
@@ -119,6 +118,20 @@ mod tests {
         Ok(crate::respond::ok())
     }
 
+    #[derive(serde::Serialize)]
+    struct Greeting {
+        hello: String,
+    }
+
+    #[crate::handler(name = "Greet")]
+    async fn greet(
+        _ctx: &mut RequestContext<(), ()>,
+        _params: crate::RouteParameters,
+    ) -> Result<(Status, Json<Greeting>), BasicError> {
+        let hello = String::from("world");
+        Ok((Status::CREATED, Json(Greeting { hello })))
+    }
+
     // struct EchoHandler;
     // #[async_trait]
     // impl Handler<(), (), BasicError> for EchoHandler {
@@ -144,26 +157,34 @@ mod tests {
             }
         ]);
 
-        let ctx = Arc::new(());
-
         let mut request = HttpRequest::new(HttpBody::empty());
         *request.uri_mut() = hyper::http::uri::Uri::from_static("http://localhost/abc/123");
         *request.method_mut() = Method::GET;
 
-        let mut req_ctx = RequestContext {
-            global: ctx.clone(),
-            local: (),
-            request,
-        };
+        let mut req_ctx = context(request);
 
         let path = req_ctx.request.uri().path().trim_matches('/');
         match router.route(path, &Method::GET) {
-            RouterResult::Found(h, params) => {
+            RouterResult::Found(h, RouteMatch { params, .. }) => {
                 assert!(h.handle(&mut req_ctx, params).await.is_ok())
             }
             RouterResult::Options(_) => panic!("got unexpected options"),
-            RouterResult::MethodNotFound => panic!("method not found"),
+            RouterResult::MethodNotFound(_) => panic!("method not found"),
             RouterResult::PathNotFound => panic!("path not found"),
+            RouterResult::Redirect(..) => panic!("got unexpected redirect"),
+            RouterResult::InvalidPath => panic!("invalid path"),
+        };
+    }
+
+    #[tokio::test]
+    async fn handler_macro_converts_responses() {
+        let mut ctx = context(HttpRequest::new(HttpBody::empty()));
+        let resp = match Greet.handle(&mut ctx, RouteParameters::new()).await {
+            Ok(resp) => HttpResponse::from(resp),
+            Err(e) => match e {},
         };
+        assert_eq!(Status::CREATED, resp.status());
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(r#"{"hello":"world"}"#, body);
     }
 }
@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+
+/// How a router handles percent-encoded slashes (`%2F`) in a path segment
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncodedSlashes {
+    /// Decode them like any other character, so they can appear in parameter
+    /// values (but never split a segment)
+    #[default]
+    Decode,
+    /// Leave them encoded as `%2F` in the segment
+    Keep,
+    /// Respond to the request with a 400
+    Reject,
+}
+
+/// How a router handles `.` and `..` segments (including encoded ones like
+/// `%2E%2E`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DotSegments {
+    /// Remove `.` segments and have `..` remove the segment before it (never
+    /// going above the root), like a browser would
+    #[default]
+    Resolve,
+    /// Respond to the request with a 400
+    Reject,
+    /// Match them like any other segment
+    Literal,
+}
+
+/// Settings for how request paths are normalized before they are routed
+#[derive(Clone, Debug)]
+pub(crate) struct Normalization {
+    pub(crate) collapse_slashes: bool,
+    pub(crate) dot_segments: DotSegments,
+    pub(crate) encoded_slashes: EncodedSlashes,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            collapse_slashes: true,
+            dot_segments: DotSegments::default(),
+            encoded_slashes: EncodedSlashes::default(),
+        }
+    }
+}
+
+/// The percent-decoded segments of a request path
+pub(crate) struct NormalizedPath<'a> {
+    pub(crate) segments: Vec<Cow<'a, str>>,
+    pub(crate) trailing_slash: bool,
+    kept_slashes: bool,
+}
+
+impl Normalization {
+    /// Split a request path into decoded segments. Returns `None` if the path
+    /// is invalid (malformed percent-encoding or non UTF-8 segments) or is
+    /// rejected by the settings.
+    pub(crate) fn normalize<'a>(&self, path: &'a str) -> Option<NormalizedPath<'a>> {
+        let inner = path.trim_matches('/');
+        let mut trailing_slash = !inner.is_empty() && path.ends_with('/');
+        let kept_slashes = self.encoded_slashes == EncodedSlashes::Keep;
+
+        let mut segments = Vec::new();
+        if inner.is_empty() {
+            return Some(NormalizedPath {
+                segments,
+                trailing_slash,
+                kept_slashes,
+            });
+        }
+
+        let raw_segments = inner.split('/').collect::<Vec<&str>>();
+        for (i, raw) in raw_segments.iter().enumerate() {
+            if raw.is_empty() && self.collapse_slashes {
+                continue;
+            }
+
+            let segment = match self.encoded_slashes {
+                EncodedSlashes::Decode | EncodedSlashes::Keep => decode(raw, kept_slashes)?,
+                EncodedSlashes::Reject => {
+                    let segment = decode(raw, false)?;
+                    if segment.contains('/') {
+                        return None;
+                    }
+                    segment
+                }
+            };
+
+            let is_dot = segment == "." || segment == "..";
+            match self.dot_segments {
+                DotSegments::Resolve if is_dot => {
+                    if segment == ".." {
+                        segments.pop();
+                    }
+                    // A trailing dot segment leaves a trailing slash
+                    // (`/a/b/..` is `/a/`)
+                    if i + 1 == raw_segments.len() {
+                        trailing_slash = true;
+                    }
+                    continue;
+                }
+                DotSegments::Reject if is_dot => return None,
+                _ => {}
+            }
+            segments.push(segment);
+        }
+
+        Some(NormalizedPath {
+            trailing_slash: trailing_slash && !segments.is_empty(),
+            segments,
+            kept_slashes,
+        })
+    }
+}
+
+impl NormalizedPath<'_> {
+    /// Rebuild the (percent-encoded) path from the segments, e.g. to redirect
+    /// to
+    pub(crate) fn to_path(&self, trailing_slash: bool) -> String {
+        // Leading empty segments (kept if slashes aren't collapsed) would
+        // start the path with `//`, which redirects to another host
+        let segments = self
+            .segments
+            .iter()
+            .skip_while(|segment| segment.is_empty());
+
+        let mut path = String::new();
+        for segment in segments {
+            path.push('/');
+            encode(segment, self.kept_slashes, &mut path);
+        }
+        if trailing_slash || path.is_empty() {
+            path.push('/');
+        }
+        path
+    }
+}
+
+/// Percent-encode a path segment, optionally leaving encoded slashes (kept by
+/// `EncodedSlashes::Keep`) as they are
+fn encode(segment: &str, kept_slashes: bool, encoded: &mut String) {
+    let bytes = segment.as_bytes();
+    for (i, byte) in bytes.iter().enumerate() {
+        let is_kept_slash = kept_slashes
+            && *byte == b'%'
+            && bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.eq_ignore_ascii_case(b"2f"));
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(byte) || is_kept_slash {
+            encoded.push(char::from(*byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+/// Percent-decode a path segment, optionally leaving `%2F` encoded
+fn decode(segment: &str, keep_slashes: bool) -> Option<Cow<'_, str>> {
+    if !segment.contains('%') {
+        return Some(Cow::Borrowed(segment));
+    }
+
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+            if byte == b'/' && keep_slashes {
+                decoded.extend_from_slice(&bytes[i..i + 3]);
+            } else {
+                decoded.push(byte);
+            }
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok().map(Cow::Owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(normalization: &Normalization, path: &str) -> Option<(Vec<String>, bool)> {
+        normalization.normalize(path).map(|normalized| {
+            let segments = normalized
+                .segments
+                .iter()
+                .map(|segment| segment.to_string())
+                .collect();
+            (segments, normalized.trailing_slash)
+        })
+    }
+
+    fn owned(segments: &[&str], trailing_slash: bool) -> Option<(Vec<String>, bool)> {
+        Some((
+            segments.iter().map(|s| String::from(*s)).collect(),
+            trailing_slash,
+        ))
+    }
+
+    #[test]
+    fn decoding_works() {
+        let normalization = Normalization::default();
+        assert_eq!(
+            owned(&["users", "John Doe", "€"], false),
+            segments(&normalization, "/users/John%20Doe/%E2%82%AC")
+        );
+        assert_eq!(None, segments(&normalization, "/users/%zz"));
+        assert_eq!(None, segments(&normalization, "/users/%2"));
+        assert_eq!(None, segments(&normalization, "/users/%FF"));
+
+        assert_eq!(owned(&["a/b"], false), segments(&normalization, "/a%2Fb"));
+        let keep = Normalization {
+            encoded_slashes: EncodedSlashes::Keep,
+            ..Normalization::default()
+        };
+        assert_eq!(owned(&["a%2fb c"], false), segments(&keep, "/a%2fb%20c"));
+        let reject = Normalization {
+            encoded_slashes: EncodedSlashes::Reject,
+            ..Normalization::default()
+        };
+        assert_eq!(None, segments(&reject, "/a%2Fb"));
+    }
+
+    #[test]
+    fn slashes_and_dots_work() {
+        let normalization = Normalization::default();
+        assert_eq!(owned(&[], false), segments(&normalization, "/"));
+        assert_eq!(
+            owned(&["a", "b"], true),
+            segments(&normalization, "//a///b//")
+        );
+        assert_eq!(
+            owned(&["a", "c"], false),
+            segments(&normalization, "/a/./b/../c")
+        );
+        assert_eq!(owned(&["a"], false), segments(&normalization, "/../../a"));
+        assert_eq!(owned(&["a"], true), segments(&normalization, "/a/b/%2E%2e"));
+        assert_eq!(owned(&[], false), segments(&normalization, "/a/.."));
+
+        let literal = Normalization {
+            collapse_slashes: false,
+            dot_segments: DotSegments::Literal,
+            ..Normalization::default()
+        };
+        assert_eq!(
+            owned(&["a", "", ".", ".."], false),
+            segments(&literal, "/a//./..")
+        );
+        let reject = Normalization {
+            dot_segments: DotSegments::Reject,
+            ..Normalization::default()
+        };
+        assert_eq!(None, segments(&reject, "/a/../b"));
+    }
+
+    #[test]
+    fn to_path_works() {
+        let normalization = Normalization::default();
+        let normalized = normalization.normalize("/a/./John%20Doe/%2F?/").unwrap();
+        assert_eq!("/a/John%20Doe/%2F%3F/", normalized.to_path(true));
+        assert_eq!("/a/John%20Doe/%2F%3F", normalized.to_path(false));
+        assert_eq!("/", normalization.normalize("/").unwrap().to_path(false));
+
+        let keep = Normalization {
+            encoded_slashes: EncodedSlashes::Keep,
+            ..Normalization::default()
+        };
+        let normalized = keep.normalize("/a%2Fb").unwrap();
+        assert_eq!("/a%2Fb", normalized.to_path(false));
+
+        let uncollapsed = Normalization {
+            collapse_slashes: false,
+            ..Normalization::default()
+        };
+        for (path, expected) in [
+            ("//evil.com/..", "/"),
+            ("/.//evil.com/", "/evil.com/"),
+            ("/a/..//evil.com//x", "/evil.com//x"),
+        ] {
+            let normalized = uncollapsed.normalize(path).unwrap();
+            assert_eq!(expected, normalized.to_path(path.ends_with('/')));
+        }
+    }
+}
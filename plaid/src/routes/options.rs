@@ -0,0 +1,110 @@
+use super::guards::{Guard, HostPattern};
+use crate::prelude::*;
+
+/// Options for a single route, given to `Router::add_with_options`. Unset
+/// options fall back to the router's.
+///
+/// ## Guards
+///
+/// Routes can also be guarded by conditions on the request's host, headers
+/// or content type. If none of a path's routes pass their guards, the router
+/// moves on to the next route that matches the path (e.g. a parameter route
+/// instead of a static one), as if the path didn't match. Several routes can
+/// be added for the same path and method as long as they have different
+/// guards (custom guards are always treated as different, unless they're
+/// cloned from the same options). They are tried in the order they were
+/// added, with unguarded routes last.
+///
+/// ```ignore
+/// router.add_with_options(
+///     vec![Method::GET],
+///     "/users",
+///     RouteOptions::new().host(":tenant.example.com"),
+///     TenantUsersHandler {},
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RouteOptions {
+    pub(crate) trailing_slash: Option<TrailingSlash>,
+    pub(crate) guards: Vec<Guard>,
+    pub(crate) body_limit: Option<usize>,
+}
+
+impl RouteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How to handle requests for this route that differ from it by a
+    /// trailing slash (see [`TrailingSlash`]). Routes for the same path share
+    /// the policy, so they can't set different ones.
+    pub fn trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = Some(policy);
+        self
+    }
+
+    /// The most bytes the route's request bodies may have, replacing the
+    /// router's and server's limits (see `Router::body_limit`)
+    pub fn body_limit(mut self, max_bytes: usize) -> Self {
+        self.body_limit = Some(max_bytes);
+        self
+    }
+
+    /// Only match requests for a host, like `admin.example.com`. Labels
+    /// starting with `:` (e.g. `:tenant.example.com`) match any label and are
+    /// added to the route's named parameters (so they can't share a name with
+    /// a path parameter). Hosts are compared ignoring case and port.
+    pub fn host(mut self, pattern: &str) -> Self {
+        self.guards.push(Guard::Host(HostPattern::new(pattern)));
+        self
+    }
+
+    /// Only match requests with a header set to `value`, like
+    /// `Accept-Version: 2`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or value aren't valid for a header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = hyper::header::HeaderName::from_bytes(name.as_bytes())
+            .unwrap_or_else(|e| panic!("Invalid guard header name {:?}: {}", name, e));
+        let value = hyper::header::HeaderValue::from_str(value)
+            .unwrap_or_else(|e| panic!("Invalid guard header value {:?}: {}", value, e));
+        self.guards.push(Guard::Header(name, value));
+        self
+    }
+
+    /// Only match requests with a `Content-Type` of this media type (ignoring
+    /// parameters like `charset`). The subtype can be a wildcard, like
+    /// `text/*`.
+    pub fn content_type(mut self, media_type: &str) -> Self {
+        self.guards
+            .push(Guard::ContentType(String::from(media_type)));
+        self
+    }
+
+    /// Only match requests that pass some predicate
+    pub fn guard(
+        mut self,
+        predicate: impl Fn(&HttpRequest) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.guards.push(Guard::custom(predicate));
+        self
+    }
+}
+
+/// How a router handles a request path that only differs from a route by a
+/// trailing slash. The form a route was added with (e.g. `/users` or
+/// `/users/`) is its canonical form.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Only the canonical form matches
+    #[default]
+    Strict,
+    /// Both forms match the route
+    MatchBoth,
+    /// Redirect to the canonical form (keeping the query string) with the
+    /// given status, e.g. `Status::MOVED_PERMANENTLY` or
+    /// `Status::PERMANENT_REDIRECT` (which keeps the request method)
+    Redirect(Status),
+}
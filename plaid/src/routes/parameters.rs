@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+/// A request's matched route: the template the route was added with (e.g.
+/// `/users/:id{i32}`) and the parameters parsed from the request path.
+#[derive(Clone, Debug)]
+pub struct RouteMatch {
+    pub template: String,
+    /// The request's path as it was matched: decoded, normalized (see
+    /// `Router::dot_segments`) and encoded again. It always starts with a
+    /// single `/`, so it's safe to redirect to.
+    pub path: String,
+    pub params: RouteParameters,
+    /// The route's own body limit, if it was added with one (see
+    /// `RouteOptions::body_limit`)
+    pub body_limit: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RouteParameters {
+    pub named: HashMap<String, Parameter>,
+    pub ordered: Vec<Parameter>,
+}
+
+impl RouteParameters {
+    pub(crate) fn new() -> Self {
+        Self {
+            named: HashMap::new(),
+            ordered: Vec::new(),
+        }
+    }
+
+    pub fn first_i32(&self) -> Option<i32> {
+        if let Some(Parameter::I32(first)) = self.ordered.first() {
+            Some(*first)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_i32(&self, name: &str) -> Option<i32> {
+        if let Some(Parameter::I32(value)) = self.named.get(name) {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<String> {
+        if let Some(Parameter::String(value)) = self.named.get(name) {
+            Some(value.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Get a named parameter as some [`FromParam`] type
+    pub fn get<T: FromParam>(&self, name: &str) -> Result<T, ParameterError> {
+        let key = ParameterKey::Named(String::from(name));
+        match self.named.get(name) {
+            Some(param) => Self::parse_as(key, param),
+            None => Err(ParameterError::Missing(key)),
+        }
+    }
+
+    /// Get a parameter by its position in the route as some [`FromParam`]
+    /// type
+    pub fn nth<T: FromParam>(&self, index: usize) -> Result<T, ParameterError> {
+        let key = ParameterKey::Index(index);
+        match self.ordered.get(index) {
+            Some(param) => Self::parse_as(key, param),
+            None => Err(ParameterError::Missing(key)),
+        }
+    }
+
+    /// Deserialize the named parameters into some type using `serde`. Values
+    /// are deserialized from their string form (like a query string), so the
+    /// target's field types don't need to match the route's type hints.
+    pub fn deserialize<T>(&self) -> Result<T, ParameterError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let pairs = self
+            .named
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_string()))
+            .collect::<Vec<(&str, String)>>();
+
+        // Serializing string pairs can't fail
+        let encoded = serde_urlencoded::to_string(pairs).unwrap_or_default();
+        let deserializer =
+            serde_urlencoded::Deserializer::new(form_urlencoded::parse(encoded.as_bytes()));
+        serde_path_to_error::deserialize(deserializer).map_err(ParameterError::Deserialize)
+    }
+
+    fn parse_as<T: FromParam>(key: ParameterKey, param: &Parameter) -> Result<T, ParameterError> {
+        param.parse().ok_or_else(|| ParameterError::WrongType {
+            key,
+            value: param.to_string(),
+            expected: std::any::type_name::<T>(),
+        })
+    }
+}
+
+/// Identifies a parameter in a [`ParameterError`]
+#[derive(Debug)]
+pub enum ParameterKey {
+    Named(String),
+    Index(usize),
+}
+
+impl std::fmt::Display for ParameterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterKey::Named(name) => write!(f, "`{}`", name),
+            ParameterKey::Index(index) => write!(f, "at position {}", index),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParameterError {
+    Missing(ParameterKey),
+    WrongType {
+        key: ParameterKey,
+        value: String,
+        expected: &'static str,
+    },
+    Deserialize(serde_path_to_error::Error<serde_urlencoded::de::Error>),
+}
+
+impl std::fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParameterError::Missing(ref key) => write!(f, "Missing route parameter {}", key),
+            ParameterError::WrongType {
+                ref key,
+                ref value,
+                expected,
+            } => write!(
+                f,
+                "Route parameter {} ({:?}) could not be parsed as {}",
+                key, value, expected
+            ),
+            ParameterError::Deserialize(ref e) => {
+                write!(f, "Failed to deserialize route parameters: {}", e)
+            }
+        }
+    }
+}
+
+/// A parsed route parameter.
+///
+/// Parameters hinted with a type registered through [`FromParam`] (other than
+/// the built-in `String`, `u32` and `i32` variants) are validated when the
+/// route is matched and kept as their raw segment in `Custom`. Use
+/// [`Parameter::parse`] to get the typed value back, which parses the segment
+/// a second time (so `from_param` should be cheap and give the same result
+/// each time).
+#[derive(Clone, Debug)]
+pub enum Parameter {
+    String(String),
+    U32(u32),
+    I32(i32),
+    Custom(String),
+}
+
+impl Parameter {
+    /// Parse this parameter as some [`FromParam`] type
+    pub fn parse<T: FromParam>(&self) -> Option<T> {
+        match self {
+            Parameter::String(s) | Parameter::Custom(s) => T::from_param(s),
+            Parameter::U32(n) => T::from_param(&n.to_string()),
+            Parameter::I32(n) => T::from_param(&n.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameter::String(s) | Parameter::Custom(s) => write!(f, "{}", s),
+            Parameter::U32(n) => write!(f, "{}", n),
+            Parameter::I32(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// # FromParam
+///
+/// Types that can be parsed from a single route segment. Once registered on a
+/// router by hint name (see `Router::parameter_type`), the type can be used in
+/// routes like `/users/:id{Uuid}`, and segments that fail to parse will not
+/// match the route.
+///
+/// ```ignore
+/// enum Color { Red, Green }
+///
+/// impl FromParam for Color {
+///     fn from_param(param: &str) -> Option<Self> {
+///         match param {
+///             "red" => Some(Color::Red),
+///             "green" => Some(Color::Green),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// let router = Router::new().parameter_type::<Color>("Color");
+/// ```
+pub trait FromParam: Sized {
+    fn from_param(param: &str) -> Option<Self>;
+}
+
+macro_rules! impl_from_param {
+    ($($ty:ty),*) => {
+        $(
+            impl FromParam for $ty {
+                fn from_param(param: &str) -> Option<Self> {
+                    param.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_from_param!(
+    String,
+    u8,
+    u16,
+    u32,
+    u64,
+    i8,
+    i16,
+    i32,
+    i64,
+    f32,
+    f64,
+    bool,
+    uuid::Uuid
+);
+
+pub(crate) type ParameterParser = fn(&str) -> Option<Parameter>;
+
+/// Parsers for type hinted parameters, by hint name
+#[derive(Clone)]
+pub(crate) struct ParameterTypes {
+    parsers: HashMap<String, ParameterParser>,
+}
+
+/// Hint used for parameters without a type hint
+pub(crate) const DEFAULT_PARAMETER_TYPE: &str = "String";
+
+/// Hints parsed into their own `Parameter` variants, which accessors like
+/// `RouteParameters::get_i32` rely on
+const BUILT_IN_PARAMETER_TYPES: [&str; 3] = [DEFAULT_PARAMETER_TYPE, "u32", "i32"];
+
+impl Default for ParameterTypes {
+    fn default() -> Self {
+        let mut types = Self {
+            parsers: HashMap::new(),
+        };
+        types.insert(DEFAULT_PARAMETER_TYPE, |s| {
+            Some(Parameter::String(String::from(s)))
+        });
+        types.insert("u32", |s| s.parse().ok().map(Parameter::U32));
+        types.insert("i32", |s| s.parse().ok().map(Parameter::I32));
+        types.register::<u8>("u8");
+        types.register::<u16>("u16");
+        types.register::<u64>("u64");
+        types.register::<i8>("i8");
+        types.register::<i16>("i16");
+        types.register::<i64>("i64");
+        types.register::<f32>("f32");
+        types.register::<f64>("f64");
+        types.register::<bool>("bool");
+        types.register::<uuid::Uuid>("Uuid");
+        types
+    }
+}
+
+impl ParameterTypes {
+    /// Register a custom type for a hint
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hint is `String`, `u32` or `i32`, which can't be
+    /// replaced
+    pub(crate) fn register<T: FromParam>(&mut self, hint: &str) {
+        if BUILT_IN_PARAMETER_TYPES.contains(&hint) {
+            panic!(
+                "Parameter type {{{}}} is built in and can't be replaced",
+                hint
+            );
+        }
+        self.insert(hint, parse_custom::<T>);
+    }
+
+    /// Add the parsers from `other` for any hints not registered here
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (hint, parser) in other.parsers.iter() {
+            self.parsers.entry(hint.clone()).or_insert(*parser);
+        }
+    }
+
+    pub(crate) fn get(&self, hint: &str) -> Option<ParameterParser> {
+        self.parsers.get(hint).copied()
+    }
+
+    fn insert(&mut self, hint: &str, parser: ParameterParser) {
+        self.parsers.insert(String::from(hint), parser);
+    }
+}
+
+/// Validate a segment as `T`. The parsed value is dropped, since `Parameter`
+/// can't hold arbitrary types, and is parsed again by `Parameter::parse`.
+fn parse_custom<T: FromParam>(param: &str) -> Option<Parameter> {
+    T::from_param(param).map(|_| Parameter::Custom(String::from(param)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> RouteParameters {
+        let mut params = RouteParameters::new();
+        for (name, value) in [
+            ("name", Parameter::String(String::from("plaid"))),
+            ("id", Parameter::I32(-5)),
+            (
+                "uuid",
+                Parameter::Custom(String::from("936da01f-9abd-4d9d-80c7-02af85c822a8")),
+            ),
+        ] {
+            params.ordered.push(value.clone());
+            params.named.insert(String::from(name), value);
+        }
+        params
+    }
+
+    #[test]
+    fn typed_accessors_work() {
+        let params = params();
+        assert_eq!("plaid", params.get::<String>("name").unwrap());
+        assert_eq!(-5, params.get::<i64>("id").unwrap());
+        assert_eq!(-5, params.nth::<i32>(1).unwrap());
+        assert!(params.nth::<uuid::Uuid>(2).is_ok());
+
+        assert!(matches!(
+            params.get::<u32>("id"),
+            Err(ParameterError::WrongType {
+                key: ParameterKey::Named(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            params.get::<u32>("missing"),
+            Err(ParameterError::Missing(ParameterKey::Named(_)))
+        ));
+        assert!(matches!(
+            params.nth::<u32>(3),
+            Err(ParameterError::Missing(ParameterKey::Index(3)))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Parameter type {i32} is built in")]
+    fn built_in_parameter_types_cant_be_replaced() {
+        ParameterTypes::default().register::<i64>("i32");
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Target {
+        name: String,
+        id: i32,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct BadTarget {
+        #[allow(dead_code)]
+        name: u32,
+    }
+
+    #[test]
+    fn deserialize_works() {
+        let params = params();
+        let target: Target = params.deserialize().unwrap();
+        assert_eq!("plaid", target.name);
+        assert_eq!(-5, target.id);
+
+        match params.deserialize::<BadTarget>() {
+            Err(ParameterError::Deserialize(e)) => assert_eq!("name", e.path().to_string()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
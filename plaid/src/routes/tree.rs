@@ -1,6 +1,15 @@
-use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
-use crate::WrappedHandler;
+use super::guards::{self, Captures, Guard};
+use super::options::{RouteOptions, TrailingSlash};
+use super::parameters::{
+    FromParam, Parameter, ParameterParser, ParameterTypes, RouteParameters, DEFAULT_PARAMETER_TYPE,
+};
+use crate::{HttpRequest, WrappedHandler};
 
 pub struct RouteTree<G, L, E>
 where
@@ -8,6 +17,7 @@ where
     L: 'static,
 {
     root: StaticNode<G, L, E>,
+    parameter_types: ParameterTypes,
 }
 
 struct InsertionContext<'a, G, L, E>
@@ -15,110 +25,327 @@ where
     G: 'static,
     L: 'static,
 {
-    parts: std::str::Split<'a, char>,
+    parts: std::slice::Iter<'a, Segment>,
+    path: &'a str,
     methods: Vec<hyper::Method>,
     handler: WrappedHandler<G, L, E>,
+    options: &'a RouteOptions,
 }
 
 impl<G, L, E> RouteTree<G, L, E> {
     pub fn new() -> Self {
         RouteTree {
-            root: StaticNode {
-                // path: String::new(),
-                priority: 0,
-                static_children: StaticChildren::new(),
-                param_children: ParameterChildren::new(),
-                routes: MethodMap::new(),
-            },
+            root: StaticNode::new(),
+            parameter_types: ParameterTypes::default(),
         }
     }
 
+    /// Register a [`FromParam`] type to use for parameters hinted with
+    /// `:name{hint}`. Types must be registered before adding routes that use
+    /// them, and can't replace the built-in `String`, `u32` and `i32` hints
+    /// (which panics).
+    pub fn register_parameter_type<T: FromParam>(&mut self, hint: &str) {
+        self.parameter_types.register::<T>(hint);
+    }
+
+    /// Add a route to the tree, failing if the path is malformed or
+    /// conflicts with a route that was already added. The tree is unchanged
+    /// if an error is returned.
     pub fn add_route(
         &mut self,
         methods: &[hyper::Method],
-        path: &'static str,
+        path: &str,
         handler: WrappedHandler<G, L, E>,
-    ) {
-        let path = path.trim_matches('/');
+        options: &RouteOptions,
+    ) -> Result<(), RouteError> {
+        let segments = Segment::parse_route(path, &self.parameter_types, options)?;
 
         let mut ctx = InsertionContext {
-            parts: path.split('/'),
+            parts: segments.iter(),
+            path,
             methods: methods.to_vec(),
             handler,
+            options,
         };
-        if path.is_empty() {
-            // Set this as the root
-            self.root.set(&ctx);
-        } else {
-            // Insert as child of the root
-            self.root.insert(&mut ctx);
-        }
+        self.root.insert(&mut ctx)
     }
 
-    // pub fn add_tree(&mut self, tree: RouteTree<R>) {
-    //     unimplemented!()
-    // }
+    /// Check that a route could be added to the tree (see
+    /// [`RouteTree::add_route`]), without adding it
+    pub(crate) fn check_route(
+        &self,
+        methods: &[hyper::Method],
+        path: &str,
+        options: &RouteOptions,
+    ) -> Result<(), RouteError> {
+        let segments = Segment::parse_route(path, &self.parameter_types, options)?;
+        self.root
+            .branches
+            .check(&mut segments.iter(), path, methods, options)
+    }
 
-    pub fn route_to(&self, path: &str) -> Option<(&MethodMap<G, L, E>, RouteParameters)> {
-        let mut params = RouteParameters::new();
-        if path.is_empty() {
-            Some(&self.root.routes)
-        } else {
-            let mut path_parts = path.split('/');
-            self.root.find(&mut path_parts, &mut params)
-        }
-        .map(|method_map| (method_map, params))
+    /// Every leaf in the tree that has routes
+    pub(crate) fn entries(&self) -> Vec<&Leaf<G, L, E>> {
+        let mut entries = Vec::new();
+        self.root.branches.collect(&mut entries);
+        entries
+    }
+
+    /// Replace every handler in the tree
+    pub(crate) fn map_handlers(
+        &mut self,
+        f: &mut dyn FnMut(WrappedHandler<G, L, E>) -> WrappedHandler<G, L, E>,
+    ) {
+        self.root.branches.map_handlers(f);
+    }
+
+    /// Register any parameter types from `other` that aren't known to this
+    /// tree, so routes grafted from it can be added
+    pub(crate) fn merge_parameter_types(&mut self, other: &Self) {
+        self.parameter_types.merge(&other.parameter_types);
+    }
+
+    /// Every route in the tree, sorted by template, with the methods it
+    /// handles
+    pub fn routes(&self) -> impl Iterator<Item = (&str, Vec<hyper::Method>)> {
+        let mut entries = self.entries();
+        entries.sort_by_key(|leaf| leaf.template());
+        entries
+            .into_iter()
+            .map(|leaf| (leaf.template(), sorted_methods(&leaf.routes)))
+    }
+
+    /// Find the leaf for the (decoded) segments of a request's path, along
+    /// with the parameters parsed from them. Static segments are compared
+    /// ignoring ASCII case if `case_insensitive` is set. Leaves where none of
+    /// the routes' guards pass for the request are skipped.
+    pub(crate) fn route_to(
+        &self,
+        path_parts: &[&str],
+        case_insensitive: bool,
+        request: &HttpRequest,
+    ) -> Option<(&Leaf<G, L, E>, RouteParameters)> {
+        let mut search = Search {
+            params: RouteParameters::new(),
+            case_insensitive,
+            request,
+        };
+        self.root
+            .branches
+            .find(path_parts, &mut search)
+            .map(|leaf| (leaf, search.params))
+    }
+}
+
+/// Prints the tree one segment per line, indented by depth, with the methods
+/// handled at each segment that ends a route:
+///
+/// ```txt
+/// /
+///   users [GET, POST]
+///     :id{i32} [GET, DELETE]
+///     *rest [GET]
+/// ```
+impl<G, L, E> std::fmt::Debug for RouteTree<G, L, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.root.branches.fmt_tree(f, "/", 0)
+    }
+}
+
+/// A route's handler, the guards a request must pass to reach it and its
+/// body limit
+pub(crate) struct Endpoint<G, L, E>
+where
+    G: 'static,
+    L: 'static,
+{
+    pub(crate) handler: WrappedHandler<G, L, E>,
+    pub(crate) guards: Vec<Guard>,
+    pub(crate) body_limit: Option<usize>,
+}
+
+impl<G, L, E> Endpoint<G, L, E> {
+    /// Check the endpoint's guards, returning any parameters they captured
+    pub(crate) fn check(&self, request: &HttpRequest) -> Option<Captures> {
+        guards::check_all(&self.guards, request)
     }
 }
 
 // TODO: maybe just make this a list of tuples with lookup by method?
 // since it'll never be big enough to need a hash
-type MethodMap<G, L, E> = HashMap<hyper::Method, WrappedHandler<G, L, E>>;
+/// Endpoints by method, in the order they should be tried
+pub(crate) type MethodMap<G, L, E> = HashMap<hyper::Method, Vec<Endpoint<G, L, E>>>;
 
-#[derive(Debug)]
-pub struct RouteParameters {
-    pub named: HashMap<String, Parameter>,
-    pub ordered: Vec<Parameter>,
+/// State of a search for a request's routes
+struct Search<'r> {
+    params: RouteParameters,
+    case_insensitive: bool,
+    request: &'r HttpRequest,
 }
 
-impl RouteParameters {
-    pub(crate) fn new() -> Self {
+/// The routes at the end of a path, by method
+pub(crate) struct Leaf<G, L, E>
+where
+    G: 'static,
+    L: 'static,
+{
+    template: Option<String>,
+    trailing_slash: Option<TrailingSlash>,
+    routes: MethodMap<G, L, E>,
+}
+
+impl<G, L, E> Leaf<G, L, E> {
+    fn new() -> Self {
         Self {
-            named: HashMap::new(),
-            ordered: Vec::new(),
+            template: None,
+            trailing_slash: None,
+            routes: MethodMap::new(),
         }
     }
 
-    pub fn first_i32(&self) -> Option<i32> {
-        if let Some(Parameter::I32(first)) = self.ordered.first() {
-            Some(*first)
-        } else {
-            None
+    /// The path of the first route added to the leaf, which is its canonical
+    /// form
+    pub(crate) fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or("/")
+    }
+
+    /// Every endpoint in the leaf, with its method
+    pub(crate) fn endpoints(&self) -> impl Iterator<Item = (&hyper::Method, &Endpoint<G, L, E>)> {
+        self.routes
+            .iter()
+            .flat_map(|(method, endpoints)| endpoints.iter().map(move |e| (method, e)))
+    }
+
+    /// The first endpoint for a method whose guards pass, with the parameters
+    /// they captured
+    pub(crate) fn endpoint(
+        &self,
+        method: &hyper::Method,
+        request: &HttpRequest,
+    ) -> Option<(&Endpoint<G, L, E>, Captures)> {
+        self.routes
+            .get(method)?
+            .iter()
+            .find_map(|endpoint| endpoint.check(request).map(|captures| (endpoint, captures)))
+    }
+
+    /// The methods with an endpoint whose guards pass
+    pub(crate) fn methods(&self, request: &HttpRequest) -> Vec<hyper::Method> {
+        sorted_methods(&self.routes)
+            .into_iter()
+            .filter(|method| self.endpoint(method, request).is_some())
+            .collect()
+    }
+
+    /// Whether any of the leaf's endpoints accept the request
+    fn accepts(&self, request: &HttpRequest) -> bool {
+        self.endpoints()
+            .any(|(_, endpoint)| endpoint.check(request).is_some())
+    }
+
+    /// Whether the canonical form of the route ends with a slash
+    pub(crate) fn has_trailing_slash(&self) -> bool {
+        let template = self.template();
+        template.len() > 1 && template.ends_with('/')
+    }
+
+    /// The trailing slash policy set for this route, if any
+    pub(crate) fn trailing_slash(&self) -> Option<TrailingSlash> {
+        self.trailing_slash
+    }
+
+    /// Options to add the leaf's routes with elsewhere
+    pub(crate) fn options(&self) -> RouteOptions {
+        RouteOptions {
+            trailing_slash: self.trailing_slash,
+            guards: Vec::new(),
+            body_limit: None,
         }
     }
 
-    pub fn get_i32(&self, name: &str) -> Option<i32> {
-        if let Some(Parameter::I32(value)) = self.named.get(name) {
-            Some(*value)
-        } else {
-            None
+    fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Add the context's handler for each of its methods. Nothing is added if
+    /// any of the methods already have an endpoint with the same guards (see
+    /// `guards::same_guards`), if the route differs from the leaf's template
+    /// by a trailing slash, or if it sets a different trailing slash policy
+    /// than the leaf's. Guarded endpoints are kept ahead of unguarded ones.
+    fn insert(&mut self, ctx: &InsertionContext<G, L, E>) -> Result<(), RouteError> {
+        let template = self.check(ctx.path, &ctx.methods, ctx.options)?;
+        let guards = &ctx.options.guards;
+        for method in ctx.methods.clone() {
+            let endpoints = self.routes.entry(method).or_default();
+            endpoints.push(Endpoint {
+                handler: ctx.handler.clone(),
+                guards: guards.clone(),
+                body_limit: ctx.options.body_limit,
+            });
+            endpoints.sort_by_key(|endpoint| endpoint.guards.is_empty());
+        }
+        self.template = Some(template);
+        if let Some(policy) = ctx.options.trailing_slash {
+            self.trailing_slash = Some(policy);
         }
+        Ok(())
     }
 
-    pub fn get_string(&self, name: &str) -> Option<String> {
-        if let Some(Parameter::String(value)) = self.named.get(name) {
-            Some(value.clone())
+    /// Check that a route could be added to the leaf (see `insert`),
+    /// returning its template
+    fn check(
+        &self,
+        path: &str,
+        methods: &[hyper::Method],
+        options: &RouteOptions,
+    ) -> Result<String, RouteError> {
+        let trimmed = path.trim_matches('/');
+        let template = if !trimmed.is_empty() && path.ends_with('/') {
+            format!("/{}/", trimmed)
         } else {
-            None
+            format!("/{}", trimmed)
+        };
+        // The leaf only has one canonical form, so `/a` and `/a/` can't both
+        // be routes
+        if let Some(existing) = self.template.as_ref().filter(|t| **t != template) {
+            return Err(RouteError::TrailingSlashConflict {
+                path: String::from(path),
+                existing: existing.clone(),
+            });
+        }
+        // The policy applies to the whole leaf, so routes that don't set one
+        // share it, but two routes can't set different ones
+        if let (Some(existing), Some(new)) = (self.trailing_slash, options.trailing_slash) {
+            if existing != new {
+                return Err(RouteError::TrailingSlashPolicyConflict {
+                    path: String::from(path),
+                });
+            }
         }
+
+        if let Some(method) = methods.iter().find(|m| {
+            self.routes.get(*m).is_some_and(|endpoints| {
+                endpoints
+                    .iter()
+                    .any(|e| guards::same_guards(&e.guards, &options.guards))
+            })
+        }) {
+            return Err(RouteError::DuplicateRoute {
+                path: String::from(path),
+                method: method.clone(),
+            });
+        }
+        Ok(template)
     }
-}
 
-#[derive(Clone, Debug)]
-pub enum Parameter {
-    String(String),
-    U32(u32),
-    I32(i32),
+    fn map_handlers(
+        &mut self,
+        f: &mut dyn FnMut(WrappedHandler<G, L, E>) -> WrappedHandler<G, L, E>,
+    ) {
+        for endpoint in self.routes.values_mut().flatten() {
+            endpoint.handler = f(endpoint.handler.clone());
+        }
+    }
 }
 
 struct StaticNode<G, L, E>
@@ -128,77 +355,202 @@ where
 {
     // path: String,
     priority: usize,
-    static_children: StaticChildren<G, L, E>,
-    param_children: ParameterChildren<G, L, E>,
-    routes: MethodMap<G, L, E>,
+    branches: Branches<G, L, E>,
 }
 
 impl<G, L, E> StaticNode<G, L, E> {
     fn new() -> Self {
         Self {
             priority: 0,
+            branches: Branches::new(),
+        }
+    }
+
+    fn insert(&mut self, ctx: &mut InsertionContext<G, L, E>) -> Result<(), RouteError> {
+        self.branches.insert(ctx)?;
+
+        // Bump priority on (successful) insertion
+        self.priority += 1;
+        Ok(())
+    }
+}
+
+/// The children and routes of a node. Static and parameter nodes only differ
+/// in how they are matched by their parent, so both hold one of these.
+struct Branches<G, L, E>
+where
+    G: 'static,
+    L: 'static,
+{
+    static_children: StaticChildren<G, L, E>,
+    param_children: ParameterChildren<G, L, E>,
+    catch_all: Option<CatchAllNode<G, L, E>>,
+    leaf: Leaf<G, L, E>,
+}
+
+impl<G, L, E> Branches<G, L, E> {
+    fn new() -> Self {
+        Self {
             static_children: StaticChildren::new(),
             param_children: ParameterChildren::new(),
-            routes: MethodMap::new(),
+            catch_all: None,
+            leaf: Leaf::new(),
         }
     }
-    fn insert(&mut self, ctx: &mut InsertionContext<G, L, E>) {
-        // Bump priority on insertion
-        self.priority += 1;
 
-        if let Some(next_part) = ctx.parts.next() {
-            // If there is another path part, insert into the correct child or
-            // generate a new one.
-            if next_part.starts_with(':') {
-                // Parameter Nodematch
-                self.param_children.insert(next_part, ctx);
-            } else {
-                self.static_children.insert(next_part, ctx);
+    fn insert(&mut self, ctx: &mut InsertionContext<G, L, E>) -> Result<(), RouteError> {
+        // If there is another path part, insert into the correct child or
+        // generate a new one.
+        match ctx.parts.next() {
+            Some(Segment::Static(part)) => self.static_children.insert(part, ctx),
+            Some(Segment::Parameter { name, ptype }) => {
+                self.param_children.insert(name, ptype, ctx)
             }
-        } else {
+            Some(Segment::CatchAll(name)) => CatchAllNode::insert(&mut self.catch_all, name, ctx),
             // If there are no more parts, this is the match. Add a leaf by
             // method.
-            self.set(ctx)
+            None => self.leaf.insert(ctx),
         }
     }
 
-    fn set(&mut self, ctx: &InsertionContext<G, L, E>) {
-        for method in ctx.methods.clone() {
-            self.routes.insert(method, ctx.handler.clone());
+    /// Check the rest of a route's segments against the existing branches,
+    /// like `insert` but without changing anything. Branches that don't exist
+    /// yet can't conflict with the route.
+    fn check(
+        &self,
+        parts: &mut std::slice::Iter<Segment>,
+        path: &str,
+        methods: &[hyper::Method],
+        options: &RouteOptions,
+    ) -> Result<(), RouteError> {
+        match parts.next() {
+            Some(Segment::Static(part)) => match self.static_children.find(part, false) {
+                Some(node) => node.branches.check(parts, path, methods, options),
+                None => Ok(()),
+            },
+            Some(Segment::Parameter { name, ptype }) => match self.param_children.get(ptype) {
+                Some(node) => {
+                    check_name(&node.name, name, path)?;
+                    node.branches.check(parts, path, methods, options)
+                }
+                None => Ok(()),
+            },
+            Some(Segment::CatchAll(name)) => match self.catch_all.as_ref() {
+                Some(node) => {
+                    check_name(&node.name, name, path)?;
+                    node.leaf.check(path, methods, options).map(drop)
+                }
+                None => Ok(()),
+            },
+            None => self.leaf.check(path, methods, options).map(drop),
         }
     }
 
-    fn find(
+    fn collect<'a>(&'a self, entries: &mut Vec<&'a Leaf<G, L, E>>) {
+        if !self.leaf.is_empty() {
+            entries.push(&self.leaf);
+        }
+        for node in self.static_children.nodes() {
+            node.branches.collect(entries);
+        }
+        for node in self.param_children.nodes() {
+            node.branches.collect(entries);
+        }
+        if let Some(node) = self.catch_all.as_ref() {
+            entries.push(&node.leaf);
+        }
+    }
+
+    fn fmt_tree(
         &self,
-        path_parts: &mut std::str::Split<char>,
-        params: &mut RouteParameters,
-    ) -> Option<&MethodMap<G, L, E>> {
-        if let Some(next_part) = path_parts.next() {
-            // Try to find an appropriate child node
-            if let Some(node) = self.static_children.find(next_part) {
-                node.find(path_parts, params)
-            } else if let Some(node) = self.param_children.find(next_part, params) {
-                node.find(path_parts, params)
-            } else {
-                None
-            }
+        f: &mut std::fmt::Formatter<'_>,
+        label: &str,
+        depth: usize,
+    ) -> std::fmt::Result {
+        fmt_node(f, label, depth, &self.leaf.routes)?;
+        for (part, node) in self.static_children.entries() {
+            node.branches.fmt_tree(f, part, depth + 1)?;
+        }
+        for node in self.param_children.nodes() {
+            node.branches.fmt_tree(f, &node.label(), depth + 1)?;
+        }
+        if let Some(node) = self.catch_all.as_ref() {
+            let label = format!("*{}", node.name.as_deref().unwrap_or_default());
+            fmt_node(f, &label, depth + 1, &node.leaf.routes)?;
+        }
+        Ok(())
+    }
+
+    fn map_handlers(
+        &mut self,
+        f: &mut dyn FnMut(WrappedHandler<G, L, E>) -> WrappedHandler<G, L, E>,
+    ) {
+        self.leaf.map_handlers(f);
+        for node in self.static_children.nodes_mut() {
+            node.branches.map_handlers(f);
+        }
+        for node in self.param_children.nodes_mut() {
+            node.branches.map_handlers(f);
+        }
+        if let Some(node) = self.catch_all.as_mut() {
+            node.leaf.map_handlers(f);
+        }
+    }
+
+    /// Find the routes for the remaining path parts. Children are tried in
+    /// order of precedence (static, typed parameters, string parameters, then
+    /// the catch-all), backtracking to the next alternative if a branch
+    /// doesn't lead to any routes. Nodes without routes (e.g. `/abc` when only
+    /// `/abc/def` is registered) don't count as a match.
+    fn find(&self, path_parts: &[&str], search: &mut Search) -> Option<&Leaf<G, L, E>> {
+        let (next_part, rest) = match path_parts.split_first() {
+            Some(split) => split,
+            // Exhausted parts and this is the endpoint
+            None if !self.leaf.accepts(search.request) => return None,
+            None => return Some(&self.leaf),
+        };
+
+        if let Some(routes) = self
+            .static_children
+            .find(next_part, search.case_insensitive)
+            .and_then(|node| node.branches.find(rest, search))
+        {
+            Some(routes)
+        } else if let Some(routes) = self.param_children.find(next_part, rest, search) {
+            Some(routes)
         } else {
-            // Else, exhausted parts and this is the endpoint
-            Some(&self.routes)
+            // The catch-all consumes the rest of the path
+            self.catch_all
+                .as_ref()
+                .filter(|node| node.leaf.accepts(search.request))
+                .map(|node| node.capture(path_parts, &mut search.params))
         }
     }
 }
 
-/// Parse the type of the paramter (default is String).
+/// The type of a parameter (default is String), parsed by a registered
+/// [`FromParam`] type.
 /// Type hinting can be done by /users/:id{i32}/profile
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
-enum ParameterType {
-    U32,
-    I32,
-    String,
+#[derive(Clone)]
+struct ParameterType {
+    hint: String,
+    parse: ParameterParser,
 }
 
-// String always last (Derive Ord/PartialOrd gives lexical ordering)
+impl PartialEq for ParameterType {
+    fn eq(&self, other: &Self) -> bool {
+        self.hint == other.hint
+    }
+}
+impl Eq for ParameterType {}
+
+impl Hash for ParameterType {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.hint.hash(state)
+    }
+}
+
+// String always last, since it accepts any segment
 impl PartialOrd for ParameterType {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -206,59 +558,132 @@ impl PartialOrd for ParameterType {
 }
 impl Ord for ParameterType {
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (ParameterType::String, ParameterType::String) => Ordering::Equal,
-            (ParameterType::String, _) => Ordering::Greater,
-            (_, ParameterType::String) => Ordering::Less,
-            (_, _) => Ordering::Equal,
+        match (self.is_string(), other.is_string()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => Ordering::Equal,
         }
     }
 }
 
-impl From<&str> for ParameterType {
-    fn from(s: &str) -> Self {
-        let lb = s.find('{');
-        let rb = s.find('}');
-
-        match (lb, rb) {
-            (Some(lb), Some(rb)) => match s.get((lb + 1)..rb) {
-                Some("u32") => ParameterType::U32,
-                Some("i32") => ParameterType::I32,
-                _ => ParameterType::String,
-            },
-            _ => ParameterType::String,
-        }
+impl ParameterType {
+    fn is_string(&self) -> bool {
+        self.hint == DEFAULT_PARAMETER_TYPE
     }
 }
 
-impl ParameterType {
+/// A validated segment of a route being added to the tree
+enum Segment {
+    Static(String),
+    Parameter {
+        name: Option<String>,
+        ptype: ParameterType,
+    },
+    CatchAll(Option<String>),
+}
+
+impl Segment {
     // TODO: could be better, but this happens during insert/setup, not
     // runtime, so it's not a huge deal
-    fn parse_name(name: &str) -> (String, Self) {
-        let name = name.trim_start_matches(':');
-        let lb = name.find('{');
-        let rb = name.find('}');
-
-        let name_part = if let Some(lb) = lb {
-            if let Some(n) = name.get(0..lb) {
-                n
+    fn parse_route(
+        path: &str,
+        types: &ParameterTypes,
+        options: &RouteOptions,
+    ) -> Result<Vec<Self>, RouteError> {
+        // Parameters captured by guards (like `:tenant.example.com`) share
+        // the route's named parameters, so their names can't repeat either
+        let mut names = HashSet::new();
+        for name in guards::capture_names(&options.guards) {
+            if !names.insert(String::from(name)) {
+                return Err(RouteError::DuplicateParameterName {
+                    path: String::from(path),
+                    name: String::from(name),
+                });
+            }
+        }
+
+        let trimmed = path.trim_matches('/');
+        if trimmed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let parts = trimmed.split('/').collect::<Vec<&str>>();
+        let mut segments = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let segment = Self::parse(path, part, types)?;
+            let name = match segment {
+                Segment::Static(_) => None,
+                Segment::Parameter { ref name, .. } => name.as_ref(),
+                Segment::CatchAll(ref name) => {
+                    if i + 1 != parts.len() {
+                        return Err(RouteError::CatchAllNotLast {
+                            path: String::from(path),
+                        });
+                    }
+                    name.as_ref()
+                }
+            };
+            if let Some(name) = name {
+                if !names.insert(name.clone()) {
+                    return Err(RouteError::DuplicateParameterName {
+                        path: String::from(path),
+                        name: name.clone(),
+                    });
+                }
+            }
+            segments.push(segment);
+        }
+        Ok(segments)
+    }
+
+    fn parse(path: &str, part: &str, types: &ParameterTypes) -> Result<Self, RouteError> {
+        let malformed = || RouteError::MalformedTypeHint {
+            path: String::from(path),
+            segment: String::from(part),
+        };
+        let non_empty = |name: &str| {
+            if name.is_empty() {
+                None
             } else {
-                name
+                Some(String::from(name))
             }
-        } else {
-            name
         };
 
-        let ptype = match (lb, rb) {
-            (Some(lb), Some(rb)) => match name.get((lb + 1)..rb) {
-                Some("u32") => ParameterType::U32,
-                Some("i32") => ParameterType::I32,
-                _ => ParameterType::String,
-            },
-            _ => ParameterType::String,
-        };
+        if let Some(name) = part.strip_prefix('*') {
+            if name.contains(['{', '}']) {
+                return Err(malformed());
+            }
+            Ok(Segment::CatchAll(non_empty(name)))
+        } else if let Some(name) = part.strip_prefix(':') {
+            // Split off the type hint, which must be the end of the segment
+            let (name, hint) = match name.find('{') {
+                Some(lb) => {
+                    let hint = name[(lb + 1)..].strip_suffix('}').ok_or_else(malformed)?;
+                    if hint.is_empty() || hint.contains(['{', '}']) {
+                        return Err(malformed());
+                    }
+                    (&name[..lb], hint)
+                }
+                None if name.contains('}') => return Err(malformed()),
+                None => (name, DEFAULT_PARAMETER_TYPE),
+            };
 
-        (String::from(name_part), ptype)
+            let parse = types.get(hint).ok_or_else(|| RouteError::UnknownTypeHint {
+                path: String::from(path),
+                hint: String::from(hint),
+            })?;
+
+            Ok(Segment::Parameter {
+                name: non_empty(name),
+                ptype: ParameterType {
+                    hint: String::from(hint),
+                    parse,
+                },
+            })
+        } else {
+            Ok(Segment::Static(String::from(part)))
+        }
     }
 }
 
@@ -269,71 +694,84 @@ where
 {
     name: Option<String>,
     ptype: ParameterType,
-    static_children: StaticChildren<G, L, E>,
-    param_children: ParameterChildren<G, L, E>,
-    routes: MethodMap<G, L, E>,
+    branches: Branches<G, L, E>,
 }
 
 impl<G, L, E> ParameterNode<G, L, E> {
-    // fn new(name: Option<String>) -> Self {
-    //     Self {
-    //         name,
-    //         ptype: ParameterType::String,
-    //         static_children: StaticChildren::new(),
-    //         param_children: ParameterChildren::new(),
-    //         routes: MethodMap::new(),
-    //     }
-    // }
-
-    fn set_name(&mut self, name: Option<String>) {
-        self.name = name;
-    }
-
-    fn insert(&mut self, ctx: &mut InsertionContext<G, L, E>) {
-        if let Some(next_part) = ctx.parts.next() {
-            // If there is another path part, insert into the correct child or
-            // generate a new one.
-            if next_part.starts_with(':') {
-                self.param_children.insert(next_part, ctx);
-            } else {
-                self.static_children.insert(next_part, ctx);
-            }
+    /// The segment as it appears in a route (e.g. `:id{i32}`)
+    fn label(&self) -> String {
+        let name = self.name.as_deref().unwrap_or_default();
+        if self.ptype.is_string() {
+            format!(":{}", name)
         } else {
-            // If there are no more parts, this is the match. Add a leaf by
-            // method.
-            self.set(ctx)
+            format!(":{}{{{}}}", name, self.ptype.hint)
         }
     }
+}
 
-    fn set(&mut self, ctx: &InsertionContext<G, L, E>) {
-        for method in ctx.methods.clone() {
-            self.routes.insert(method, ctx.handler.clone());
-        }
-    }
+/// A catch-all (`/*name`) parameter, which binds the remainder of the path
+/// (including any `/`s). Catch-alls are always leaves and are only matched
+/// after static and parameter children fail to match.
+struct CatchAllNode<G, L, E>
+where
+    G: 'static,
+    L: 'static,
+{
+    name: Option<String>,
+    leaf: Leaf<G, L, E>,
+}
 
-    fn find(
-        &self,
-        path_parts: &mut std::str::Split<char>,
-        params: &mut RouteParameters,
-    ) -> Option<&MethodMap<G, L, E>> {
-        if let Some(next_part) = path_parts.next() {
-            // Try to find an appropriate child node
-            if let Some(node) = self.static_children.find(next_part) {
-                node.find(path_parts, params)
-            } else if let Some(node) = self.param_children.find(next_part, params) {
-                node.find(path_parts, params)
-            } else {
-                None
+impl<G, L, E> CatchAllNode<G, L, E> {
+    fn insert(
+        slot: &mut Option<Self>,
+        name: &Option<String>,
+        ctx: &mut InsertionContext<G, L, E>,
+    ) -> Result<(), RouteError> {
+        let node = match slot {
+            Some(node) => {
+                check_name(&node.name, name, ctx.path)?;
+                node
             }
+            None => slot.get_or_insert_with(|| CatchAllNode {
+                name: name.clone(),
+                leaf: Leaf::new(),
+            }),
+        };
+        node.leaf.insert(ctx)
+    }
+
+    fn capture(&self, path_parts: &[&str], params: &mut RouteParameters) -> &Leaf<G, L, E> {
+        let value = Parameter::String(path_parts.join("/"));
+        if let Some(name) = self.name.clone() {
+            params.ordered.push(value.clone());
+            params.named.insert(name, value);
         } else {
-            // Else, exhausted parts and this is the endpoint
-            Some(&self.routes)
+            params.ordered.push(value);
         }
+        &self.leaf
     }
 }
 
+/// Check that a parameter (or catch-all) being added has the same name as the
+/// one already at its position
+fn check_name(
+    existing: &Option<String>,
+    name: &Option<String>,
+    path: &str,
+) -> Result<(), RouteError> {
+    if existing != name {
+        return Err(RouteError::AmbiguousParameter {
+            path: String::from(path),
+            existing: existing.clone().unwrap_or_default(),
+            new: name.clone().unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
 /// NodeChildren holds either a vec or hashmap of child nodes (static nodes)
-/// with an optional parameter node
+/// with an optional parameter node. Vecs are swapped for hashmaps once they
+/// hold more than `NODE_CHILDREN_VEC_LIMIT` nodes.
 enum Children<K, V>
 where
     K: Hash,
@@ -349,47 +787,69 @@ const NODE_CHILDREN_VEC_LIMIT: usize = 15;
 
 impl<K, V> Children<K, V>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Ord,
 {
     fn new() -> Self {
         Children::<K, V>::Few(Vec::new())
     }
 
-    // fn len(&self) -> usize {
-    //     match self {
-    //         Children::Few(ref v) => v.len(),
-    //         Children::Many(ref h) => h.len(),
-    //     }
-    // }
+    fn entries(&self) -> Vec<(&K, &V)> {
+        match self {
+            Children::Few(v) => v.iter().map(|(key, node)| (key, node.as_ref())).collect(),
+            Children::Many(h) => {
+                // Keep the order stable (e.g. for the tree's Debug output)
+                let mut entries = h
+                    .iter()
+                    .map(|(key, node)| (key, node.as_ref()))
+                    .collect::<Vec<_>>();
+                entries.sort_by_key(|(key, _)| *key);
+                entries
+            }
+        }
+    }
 
-    fn rebalance(self) -> Self {
+    fn nodes(&self) -> Vec<&V> {
+        self.entries().into_iter().map(|(_, node)| node).collect()
+    }
+
+    fn nodes_mut(&mut self) -> Vec<&mut V> {
+        match self {
+            Children::Few(v) => v.iter_mut().map(|(_, node)| node.as_mut()).collect(),
+            Children::Many(h) => h.values_mut().map(|node| node.as_mut()).collect(),
+        }
+    }
+
+    fn rebalance(&mut self) {
         if let Children::Few(v) = self {
-            Children::Many(v.into_iter().collect::<HashMap<K, Box<V>>>())
-        // let mut map = HashMap::new();
-        // for (name, node) in v.into_iter() {
-        //     let _ = map.insert(name, node);
-        // }
-        // *self = Children::Many(v);
-        } else {
-            self
+            if v.len() > NODE_CHILDREN_VEC_LIMIT {
+                let map = v.drain(..).collect::<HashMap<K, Box<V>>>();
+                *self = Children::Many(map);
+            }
         }
     }
 }
 
 impl<G, L, E> StaticChildren<G, L, E> {
-    fn find(&self, path: &str) -> Option<&Box<StaticNode<G, L, E>>> {
+    fn find(&self, path: &str, case_insensitive: bool) -> Option<&StaticNode<G, L, E>> {
+        let matches = |node_path: &str| {
+            node_path == path || (case_insensitive && node_path.eq_ignore_ascii_case(path))
+        };
         match self {
             // Linear search of Vec<Node>
             Children::Few(ref v) => {
                 for (node_path, node) in v.iter() {
-                    if path == node_path {
+                    if matches(node_path) {
                         return Some(node);
                     }
                 }
                 None
             }
-            // Lookup of HashMap<Node>
-            Children::Many(ref h) => h.get(path),
+            // Lookup of HashMap<Node> (or a linear search if ignoring case)
+            Children::Many(ref h) if case_insensitive => h
+                .iter()
+                .find(|(node_path, _)| matches(node_path))
+                .map(|(_, boxed)| boxed.as_ref()),
+            Children::Many(ref h) => h.get(path).map(|boxed| boxed.as_ref()),
         }
     }
 
@@ -407,24 +867,19 @@ impl<G, L, E> StaticChildren<G, L, E> {
         }
     }
 
-    fn insert(&mut self, path: &str, ctx: &mut InsertionContext<G, L, E>) {
+    fn insert(
+        &mut self,
+        path: &str,
+        ctx: &mut InsertionContext<G, L, E>,
+    ) -> Result<(), RouteError> {
         // Check if a child with this path exists
         if let Some(node) = self.find_mut(path) {
             // This is a child of the given node
-            node.insert(ctx);
+            node.insert(ctx)
         } else {
             // This is a new child
-            // Check if we need to rebalance
-            // TODO: rebalancing
-            // if let Children::Few(v) = self {
-            //     if v.len() >= NODE_CHILDREN_VEC_LIMIT {
-            //         *self = self.rebalance();
-            //     }
-            // }
-
-            // Add the node
             let mut new_node = Box::new(StaticNode::new());
-            new_node.insert(ctx);
+            new_node.insert(ctx)?;
             match self {
                 Children::Few(v) => {
                     v.push((String::from(path), new_node));
@@ -436,110 +891,228 @@ impl<G, L, E> StaticChildren<G, L, E> {
                     h.insert(String::from(path), new_node);
                 }
             }
+            self.rebalance();
+            Ok(())
         }
     }
 }
 
 impl<G, L, E> ParameterChildren<G, L, E> {
-    fn find(
-        &self,
-        path: &str,
-        params: &mut RouteParameters,
-    ) -> Option<&Box<ParameterNode<G, L, E>>> {
+    /// Find the first parameter child that accepts `path` and leads to some
+    /// routes for the `rest` of the path. Parameters bound by branches that
+    /// don't pan out are removed again before trying the next child.
+    fn find(&self, path: &str, rest: &[&str], search: &mut Search) -> Option<&Leaf<G, L, E>> {
         match self {
-            Children::Few(v) => v.iter().find_map(|(ptype, node)| {
-                if self.find_loop(path, ptype, node, params) {
-                    Some(node)
-                } else {
-                    None
-                }
-            }),
-            Children::Many(h) => h.iter().find_map(|(ptype, node)| {
-                if self.find_loop(path, ptype, node, params) {
-                    Some(node)
-                } else {
-                    None
-                }
-            }),
+            Children::Few(v) => v
+                .iter()
+                .find_map(|(ptype, node)| Self::find_loop(path, rest, ptype, node, search)),
+            Children::Many(h) => {
+                // Keep String parameters last, like the sorted vec
+                let mut nodes = h.iter().collect::<Vec<_>>();
+                nodes.sort_by_key(|(ptype, _)| *ptype);
+                nodes
+                    .into_iter()
+                    .find_map(|(ptype, node)| Self::find_loop(path, rest, ptype, node, search))
+            }
         }
     }
 
-    fn find_loop(
-        &self,
+    /// The child for a parameter type
+    fn get(&self, ptype: &ParameterType) -> Option<&ParameterNode<G, L, E>> {
+        match self {
+            Children::Few(v) => v.iter().find(|(t, _)| t == ptype).map(|(_, b)| b.as_ref()),
+            Children::Many(h) => h.get(ptype).map(|boxed| boxed.as_ref()),
+        }
+    }
+
+    fn find_loop<'a>(
         path: &str,
+        rest: &[&str],
         ptype: &ParameterType,
-        node: &Box<ParameterNode<G, L, E>>,
-        params: &mut RouteParameters,
-    ) -> bool {
-        if let Some(value) = match ptype {
-            ParameterType::U32 => path.parse::<u32>().ok().map(Parameter::U32),
-            ParameterType::I32 => path.parse::<i32>().ok().map(Parameter::I32),
-            ParameterType::String => Some(Parameter::String(String::from(path))),
-        } {
-            if let Some(name) = node.name.clone() {
-                params.ordered.push(value.clone());
-                params.named.insert(name, value);
-            } else {
-                params.ordered.push(value.clone());
+        node: &'a ParameterNode<G, L, E>,
+        search: &mut Search,
+    ) -> Option<&'a Leaf<G, L, E>> {
+        let value = (ptype.parse)(path)?;
+
+        search.params.ordered.push(value.clone());
+        if let Some(name) = node.name.clone() {
+            search.params.named.insert(name, value);
+        }
+
+        let found = node.branches.find(rest, search);
+        if found.is_none() {
+            search.params.ordered.pop();
+            if let Some(name) = node.name.as_ref() {
+                search.params.named.remove(name);
             }
-            true
-        } else {
-            false
         }
+        found
     }
 
-    fn insert(&mut self, path: &str, ctx: &mut InsertionContext<G, L, E>) {
-        // Try parsing the name for a type
-        let (param_name, param_type) = ParameterType::parse_name(path);
+    fn insert(
+        &mut self,
+        name: &Option<String>,
+        ptype: &ParameterType,
+        ctx: &mut InsertionContext<G, L, E>,
+    ) -> Result<(), RouteError> {
         if let Some(node) = match self {
             Children::Few(v) => v
                 .iter_mut()
-                .find(|(t, _)| *t == param_type)
+                .find(|(t, _)| t == ptype)
                 .map(|(_, b)| b.as_mut()),
-            Children::Many(h) => h.get_mut(&param_type).map(|boxed| boxed.as_mut()),
+            Children::Many(h) => h.get_mut(ptype).map(|boxed| boxed.as_mut()),
         } {
-            if let Some(n) = node.name.clone() {
-                if param_name != n {
-                    // TODO: Better error here
-                    panic!(
-                        "Parameter route defined with overlapping names and same type /{}, /{}",
-                        param_name, n
-                    );
-                }
-            } else {
-                node.set_name(Some(param_name));
-            }
-            node.insert(ctx);
+            check_name(&node.name, name, ctx.path)?;
+            node.branches.insert(ctx)
         } else {
             // This is a new child
-            // Check if we need to rebalance
-            // TODO: rebalancing
-            // if let Children::Few(v) = self {
-            //     if v.len() >= NODE_CHILDREN_VEC_LIMIT {
-            //         self.rebalance();
-            //     }
-            // }
-
-            // Add the node
             let mut new_node = Box::new(ParameterNode {
-                name: Some(param_name),
-                ptype: param_type.clone(),
-                static_children: StaticChildren::new(),
-                param_children: ParameterChildren::new(),
-                routes: MethodMap::new(),
+                name: name.clone(),
+                ptype: ptype.clone(),
+                branches: Branches::new(),
             });
-            new_node.insert(ctx);
+            new_node.branches.insert(ctx)?;
             match self {
                 Children::Few(v) => {
-                    v.push((param_type, new_node));
+                    v.push((ptype.clone(), new_node));
 
                     // Sort nodes by type (so String is always last)
                     v.sort_by(|(_, a), (_, b)| a.ptype.cmp(&b.ptype));
                 }
                 Children::Many(h) => {
-                    h.insert(param_type, new_node);
+                    h.insert(ptype.clone(), new_node);
                 }
             }
+            self.rebalance();
+            Ok(())
+        }
+    }
+}
+
+/// Methods in the order they're usually listed (e.g. in an `Allow` header),
+/// then any extension methods alphabetically
+const METHOD_ORDER: [hyper::Method; 9] = [
+    hyper::Method::GET,
+    hyper::Method::HEAD,
+    hyper::Method::POST,
+    hyper::Method::PUT,
+    hyper::Method::PATCH,
+    hyper::Method::DELETE,
+    hyper::Method::OPTIONS,
+    hyper::Method::CONNECT,
+    hyper::Method::TRACE,
+];
+
+pub(crate) fn sorted_methods<G, L, E>(routes: &MethodMap<G, L, E>) -> Vec<hyper::Method> {
+    let mut methods = routes.keys().cloned().collect::<Vec<_>>();
+    methods.sort_by(|a, b| {
+        let position = |m: &hyper::Method| METHOD_ORDER.iter().position(|o| o == m);
+        match (position(a), position(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.as_str().cmp(b.as_str()),
+        }
+    });
+    methods
+}
+
+fn fmt_node<G, L, E>(
+    f: &mut std::fmt::Formatter<'_>,
+    label: &str,
+    depth: usize,
+    routes: &MethodMap<G, L, E>,
+) -> std::fmt::Result {
+    write!(f, "{:indent$}{}", "", label, indent = depth * 2)?;
+    if !routes.is_empty() {
+        write!(f, " [{}]", join_methods(&sorted_methods(routes)))?;
+    }
+    writeln!(f)
+}
+
+/// List methods like `GET, POST`
+pub(crate) fn join_methods(methods: &[hyper::Method]) -> String {
+    methods
+        .iter()
+        .map(|m| m.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reasons a route can't be added to a [`RouteTree`]
+#[derive(Debug)]
+pub enum RouteError {
+    /// A handler is already registered for this method and path
+    DuplicateRoute { path: String, method: hyper::Method },
+    /// A parameter (or catch-all) with the same type but a different name is
+    /// already registered at this position, e.g. `/:id` and `/:name`
+    AmbiguousParameter {
+        path: String,
+        existing: String,
+        new: String,
+    },
+    /// The same parameter name is used more than once in the route
+    /// (including parameters captured by its host guards)
+    DuplicateParameterName { path: String, name: String },
+    /// A catch-all (`/*name`) is followed by more segments
+    CatchAllNotLast { path: String },
+    /// A type hint isn't registered on the router
+    UnknownTypeHint { path: String, hint: String },
+    /// A segment has an empty, unclosed or misplaced `{type}` hint
+    MalformedTypeHint { path: String, segment: String },
+    /// The route only differs from an existing one by a trailing slash, e.g.
+    /// `/users` and `/users/`
+    TrailingSlashConflict { path: String, existing: String },
+    /// The route sets a different trailing slash policy (see
+    /// `RouteOptions::trailing_slash`) than another route for the same path
+    TrailingSlashPolicyConflict { path: String },
+}
+
+impl std::fmt::Display for RouteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RouteError::DuplicateRoute { path, method } => {
+                write!(f, "Route {} {} is already defined", method, path)
+            }
+            RouteError::AmbiguousParameter {
+                path,
+                existing,
+                new,
+            } => write!(
+                f,
+                "Route {} defines parameter `{}` where `{}` is already defined with the same type",
+                path, new, existing
+            ),
+            RouteError::DuplicateParameterName { path, name } => {
+                write!(
+                    f,
+                    "Route {} defines parameter `{}` more than once",
+                    path, name
+                )
+            }
+            RouteError::CatchAllNotLast { path } => {
+                write!(f, "Route {} has a catch-all before its last segment", path)
+            }
+            RouteError::UnknownTypeHint { path, hint } => {
+                write!(f, "Route {} uses unknown parameter type {{{}}}", path, hint)
+            }
+            RouteError::MalformedTypeHint { path, segment } => {
+                write!(
+                    f,
+                    "Route {} has a malformed type hint in /{}",
+                    path, segment
+                )
+            }
+            RouteError::TrailingSlashConflict { path, existing } => write!(
+                f,
+                "Route {} only differs from route {} by a trailing slash",
+                path, existing
+            ),
+            RouteError::TrailingSlashPolicyConflict { path } => write!(
+                f,
+                "Route {} sets a different trailing slash policy than another route for the same path",
+                path
+            ),
         }
     }
 }
@@ -3,7 +3,7 @@ mod service;
 use std::sync::Arc;
 
 use crate::middleware::{Middleware, ToMiddleware};
-use crate::routes::Router;
+use crate::routes::{Resolver, Router};
 
 /// # Plaid Server
 ///
@@ -54,7 +54,9 @@ where
 {
     context: Option<Arc<GlobalCtx>>,
     // router: Option<Router<GlobalCtx, LocalCtx, Err>>,
+    resolver: Option<Arc<dyn Resolver>>,
     middleware_stack: Option<Arc<dyn Middleware<GlobalCtx, LocalCtx>>>,
+    body_limit: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -73,7 +75,9 @@ where
     fn default() -> Self {
         Self {
             context: None,
+            resolver: None,
             middleware_stack: None,
+            body_limit: None,
             // handle_error: Arc::new(crate::handlers::default_error_handler),
         }
     }
@@ -100,8 +104,18 @@ where
         self
     }
 
-    pub fn router<E: Send + Sync + 'static>(mut self, router: Router<G, L, E>) -> Self {
-        self.middleware_stack = Some(Arc::new(router));
+    pub fn router<E: Send + Sync + 'static>(mut self, mut router: Router<G, L, E>) -> Self {
+        router.apply_layers();
+        let router = Arc::new(router);
+        self.resolver = Some(router.clone());
+        self.middleware_stack = Some(router);
+        self
+    }
+
+    /// The most bytes any request body may have. Routers and routes can
+    /// replace it with their own limits (see `Router::body_limit`).
+    pub fn body_limit(mut self, max_bytes: usize) -> Self {
+        self.body_limit = Some(max_bytes);
         self
     }
 
@@ -150,10 +164,12 @@ where
     {
         let addr: std::net::SocketAddr = addr.into();
 
-        if let Some(stack) = self.middleware_stack {
+        if let (Some(stack), Some(resolver)) = (self.middleware_stack, self.resolver) {
             let service = service::Service {
                 context: self.context.ok_or(ServerError::NoContext)?,
+                resolver,
                 call_stack: stack,
+                body_limit: self.body_limit,
             };
 
             let server = hyper::Server::bind(&addr).serve(service::Generator { service });
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use crate::routes::Resolver;
 use crate::{HttpRequest, HttpResponse, Middleware, RequestContext};
 
 type PinnedFuture<T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send>>;
@@ -10,14 +11,18 @@ where
     LocalCtx: 'static,
 {
     pub(super) context: Arc<GlobalCtx>,
+    pub(super) resolver: Arc<dyn Resolver>,
     pub(super) call_stack: Arc<dyn Middleware<GlobalCtx, LocalCtx>>,
+    pub(super) body_limit: Option<usize>,
 }
 
 impl<G, L> Clone for Service<G, L> {
     fn clone(&self) -> Self {
         Self {
             context: self.context.clone(),
+            resolver: self.resolver.clone(),
             call_stack: self.call_stack.clone(),
+            body_limit: self.body_limit,
         }
     }
 }
@@ -45,11 +50,15 @@ where
     /// compiler itsn't quite smart enough to know that the `.await`s make this
     /// effectively a sync segment. (Unless I'm missing something, which I
     /// probably am...)
-    fn call(&mut self, req: HttpRequest) -> Self::Future {
+    fn call(&mut self, mut req: HttpRequest) -> Self::Future {
+        if let Some(max_bytes) = self.body_limit {
+            crate::limits::set(&mut req, max_bytes);
+        }
         let call_stack = self.call_stack.clone();
         let mut context = RequestContext {
             global: self.context.clone(),
             local: LocalCtx::default(),
+            route: self.resolver.resolve(&req),
             request: req,
         };
         Box::pin(async move { Ok(call_stack.call(&mut context).await) })
@@ -0,0 +1,42 @@
+//! Helpers shared by the crate's tests
+
+use std::sync::Arc;
+
+use crate::prelude::*;
+
+/// A context for `request`, with no global or local state
+pub(crate) fn context(request: HttpRequest) -> RequestContext<(), ()> {
+    context_with((), request)
+}
+
+/// A context for `request`, with some global state and no local state
+pub(crate) fn context_with<G>(global: G, request: HttpRequest) -> RequestContext<G, ()> {
+    RequestContext {
+        global: Arc::new(global),
+        local: (),
+        request,
+        route: None,
+    }
+}
+
+/// Pass `request` through the router, as the server would
+pub(crate) async fn call<E>(router: &Router<(), (), E>, request: HttpRequest) -> HttpResponse
+where
+    E: Send + Sync + 'static,
+{
+    call_with(router, (), request).await
+}
+
+/// Pass `request` through the router with some global state, as the server
+/// would
+pub(crate) async fn call_with<G, E>(
+    router: &Router<G, (), E>,
+    global: G,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    G: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    router.call(&mut context_with(global, request)).await
+}
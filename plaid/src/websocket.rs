@@ -0,0 +1,373 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::upgrade::Upgraded;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::prelude::*;
+
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+pub use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+pub use tokio_tungstenite::tungstenite::{Error as WebSocketError, Message};
+
+/// # WebSocket Handler
+///
+/// A [`Handler`] that upgrades requests to WebSocket connections (performing
+/// the RFC 6455 handshake) and passes each connection to a callback, along
+/// with a [`WebSocketContext`]. Add it to a router like any other handler:
+///
+/// ```ignore
+/// router.add(
+///     vec![Method::GET],
+///     "/echo",
+///     websocket(|mut socket: WebSocket, _: WebSocketContext<(), ()>| async move {
+///         while let Some(Ok(message)) = socket.recv().await {
+///             if message.is_text() || message.is_binary() {
+///                 let _ = socket.send(message).await;
+///             }
+///         }
+///     }),
+/// );
+/// ```
+///
+/// Requests that aren't valid WebSocket handshakes get a 400 (or a 426 if
+/// they ask for an unsupported version of the protocol). The callback runs on
+/// its own task once the connection is upgraded, after the 101 response has
+/// passed back through the middleware.
+pub struct WebSocketHandler<GlobalCtx, LocalCtx, F> {
+    callback: Arc<F>,
+    protocols: Vec<String>,
+    config: Option<WebSocketConfig>,
+    _context: PhantomData<fn(GlobalCtx, LocalCtx)>,
+}
+
+/// Create a [`WebSocketHandler`] from a callback that takes the upgraded
+/// socket and the request's context
+pub fn websocket<GlobalCtx, LocalCtx, F, Fut>(
+    callback: F,
+) -> WebSocketHandler<GlobalCtx, LocalCtx, F>
+where
+    F: Fn(WebSocket, WebSocketContext<GlobalCtx, LocalCtx>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    WebSocketHandler {
+        callback: Arc::new(callback),
+        protocols: Vec::new(),
+        config: None,
+        _context: PhantomData,
+    }
+}
+
+impl<G, L, F> WebSocketHandler<G, L, F> {
+    /// Subprotocols the handler supports, in order of preference. The first
+    /// one the client offers in `Sec-WebSocket-Protocol` is accepted (see
+    /// [`WebSocketContext::protocol`]). If the client doesn't offer any of
+    /// them, the connection is made without a subprotocol.
+    pub fn protocols(mut self, protocols: &[&str]) -> Self {
+        self.protocols = protocols.iter().map(|p| String::from(*p)).collect();
+        self
+    }
+
+    /// Set limits like the maximum message size
+    pub fn config(mut self, config: WebSocketConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+}
+
+/// What a WebSocket callback gets from the request that opened the
+/// connection
+pub struct WebSocketContext<GlobalCtx, LocalCtx> {
+    pub global: Arc<GlobalCtx>,
+    /// A clone of the local context, as middleware left it
+    pub local: LocalCtx,
+    pub route: Option<RouteMatch>,
+    pub params: RouteParameters,
+    pub headers: HeaderMap,
+    pub uri: hyper::Uri,
+    /// The subprotocol agreed on in the handshake, if any
+    pub protocol: Option<String>,
+}
+
+#[async_trait]
+impl<G, L, E, F, Fut> Handler<G, L, E> for WebSocketHandler<G, L, F>
+where
+    G: Send + Sync + 'static,
+    L: Clone + Send + Sync + 'static,
+    E: Send + 'static,
+    F: Fn(WebSocket, WebSocketContext<G, L>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    async fn handle(
+        &self,
+        ctx: &mut RequestContext<G, L>,
+        params: RouteParameters,
+    ) -> Result<Response, E> {
+        let key = match handshake_key(&ctx.request) {
+            Ok(key) => key,
+            Err(status) => return Ok(reject(status)),
+        };
+        let protocol = self.select_protocol(ctx.request.headers());
+
+        let socket_ctx = WebSocketContext {
+            global: ctx.global.clone(),
+            local: ctx.local.clone(),
+            route: ctx.route.clone(),
+            params,
+            headers: ctx.request.headers().clone(),
+            uri: ctx.request.uri().clone(),
+            protocol: protocol.clone(),
+        };
+        let upgrade = hyper::upgrade::on(&mut ctx.request);
+        let callback = self.callback.clone();
+        let config = self.config;
+        tokio::spawn(async move {
+            match upgrade.await {
+                Ok(upgraded) => {
+                    let stream =
+                        WebSocketStream::from_raw_socket(upgraded, Role::Server, config).await;
+                    callback(WebSocket { inner: stream }, socket_ctx).await;
+                }
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!("Failed to upgrade websocket connection: {}", e);
+                }
+            }
+        });
+
+        let mut resp = HttpResponse::new(HttpBody::empty());
+        *resp.status_mut() = Status::SWITCHING_PROTOCOLS;
+        let headers = resp.headers_mut();
+        headers.insert(
+            hyper::header::UPGRADE,
+            HeaderValue::from_static("websocket"),
+        );
+        headers.insert(
+            hyper::header::CONNECTION,
+            HeaderValue::from_static("Upgrade"),
+        );
+        if let Ok(accept) = HeaderValue::from_str(&derive_accept_key(key.as_bytes())) {
+            headers.insert(hyper::header::SEC_WEBSOCKET_ACCEPT, accept);
+        }
+        if let Some(protocol) = protocol.and_then(|p| HeaderValue::from_str(&p).ok()) {
+            headers.insert(hyper::header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        Ok(Response::Custom(resp))
+    }
+}
+
+impl<G, L, F> WebSocketHandler<G, L, F> {
+    fn select_protocol(&self, headers: &HeaderMap) -> Option<String> {
+        let offered = headers
+            .get_all(hyper::header::SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<&str>>();
+        self.protocols
+            .iter()
+            .find(|protocol| offered.contains(&protocol.as_str()))
+            .cloned()
+    }
+}
+
+/// Check that a request is a WebSocket handshake, returning its
+/// `Sec-WebSocket-Key` or the status to reject it with
+fn handshake_key(request: &HttpRequest) -> Result<String, Status> {
+    let headers = request.headers();
+    let has_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+
+    if request.method() != Method::GET
+        || !has_token(hyper::header::CONNECTION, "upgrade")
+        || !has_token(hyper::header::UPGRADE, "websocket")
+    {
+        return Err(Status::BAD_REQUEST);
+    }
+    if !has_token(hyper::header::SEC_WEBSOCKET_VERSION, "13") {
+        return Err(Status::UPGRADE_REQUIRED);
+    }
+    headers
+        .get(hyper::header::SEC_WEBSOCKET_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(String::from)
+        .ok_or(Status::BAD_REQUEST)
+}
+
+/// Respond to a request that isn't a valid handshake. A 426 lists the
+/// supported protocol version.
+fn reject(status: Status) -> Response {
+    let mut resp = HttpResponse::new(HttpBody::empty());
+    *resp.status_mut() = status;
+    if status == Status::UPGRADE_REQUIRED {
+        resp.headers_mut().insert(
+            hyper::header::SEC_WEBSOCKET_VERSION,
+            HeaderValue::from_static("13"),
+        );
+    }
+    Response::Custom(resp)
+}
+
+/// An upgraded WebSocket connection. It's a [`Stream`] of incoming messages
+/// and a [`Sink`] for outgoing ones (so it can be `split` to read and write
+/// from different tasks).
+///
+/// Pings are answered with pongs automatically while the socket is being
+/// read from.
+pub struct WebSocket {
+    inner: WebSocketStream<Upgraded>,
+}
+
+impl WebSocket {
+    /// Receive the next message, or `None` once the connection is closed
+    pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
+        self.inner.next().await
+    }
+
+    /// Send a message
+    pub async fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        self.inner.send(message).await
+    }
+
+    /// Send a ping with some payload
+    pub async fn ping(&mut self, payload: Vec<u8>) -> Result<(), WebSocketError> {
+        self.send(Message::Ping(payload)).await
+    }
+
+    /// Start closing the connection with a code and reason. Keep reading
+    /// from the socket until it returns `None` to finish the close handshake.
+    pub async fn close(&mut self, code: CloseCode, reason: &str) -> Result<(), WebSocketError> {
+        self.inner
+            .close(Some(CloseFrame {
+                code,
+                reason: reason.to_owned().into(),
+            }))
+            .await
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<Message, WebSocketError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = WebSocketError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum BasicError {}
+
+    #[tokio::test]
+    async fn websocket_handler_works() {
+        let mut router: Router<String, (), BasicError> = Router::new();
+        router.add(
+            vec![Method::GET],
+            "/echo/:room",
+            websocket(|mut socket, ctx: WebSocketContext<String, ()>| async move {
+                let room = ctx.params.get::<String>("room").unwrap();
+                let greeting = format!("{} {} {:?}", ctx.global, room, ctx.protocol);
+                socket.send(Message::Text(greeting)).await.unwrap();
+                while let Some(Ok(message)) = socket.recv().await {
+                    if message.is_text() {
+                        socket.send(message).await.unwrap();
+                    }
+                }
+            })
+            .protocols(&["chat"]),
+        );
+
+        // Plain requests to the route are rejected
+        let mut request = HttpRequest::new(HttpBody::empty());
+        *request.uri_mut() = hyper::Uri::from_static("/echo/lobby");
+        let resp = call(&router, request).await;
+        assert_eq!(Status::BAD_REQUEST, resp.status());
+
+        let router = Arc::new(router);
+        let (client_io, server_io) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |request| {
+                let router = router.clone();
+                async move { Ok::<_, hyper::Error>(call(&router, request).await) }
+            });
+            hyper::server::conn::Http::new()
+                .serve_connection(server_io, service)
+                .with_upgrades()
+                .await
+        });
+
+        let request = hyper::Request::builder()
+            .uri("ws://localhost/echo/lobby")
+            .header("host", "localhost")
+            .header("connection", "Upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("sec-websocket-protocol", "other, chat")
+            .body(())
+            .unwrap();
+        let (mut client, resp) = tokio_tungstenite::client_async(request, client_io)
+            .await
+            .unwrap();
+        assert_eq!(
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+            resp.headers()["sec-websocket-accept"]
+        );
+
+        let greeting = client.next().await.unwrap().unwrap();
+        assert_eq!(
+            Message::Text(String::from("global lobby Some(\"chat\")")),
+            greeting
+        );
+        client
+            .send(Message::Text(String::from("hello")))
+            .await
+            .unwrap();
+        assert_eq!(
+            Message::Text(String::from("hello")),
+            client.next().await.unwrap().unwrap()
+        );
+        client.close(None).await.unwrap();
+    }
+
+    async fn call(router: &Router<String, (), BasicError>, request: HttpRequest) -> HttpResponse {
+        crate::testing::call_with(router, String::from("global"), request).await
+    }
+}